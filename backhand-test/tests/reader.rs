@@ -0,0 +1,2265 @@
+use std::collections::hash_map::DefaultHasher;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use backhand::compression::{CompressionAction, Compressor};
+use backhand::kind::Kind;
+use backhand::metadata::{read_block_with_compressor, set_if_uncompressed, METADATA_MAXSIZE};
+use backhand::{
+    BackhandError, BlockSize, DirOrder, ExtractOptions, FilesystemCompressor, FilesystemReader,
+    FilesystemWriter, InnerNode, Inode, InodeInner, NodeHeader, NodeMetadata, NodeType,
+    ReadWarning, Squashfs, MIN_BLOCK_SIZE,
+};
+
+/// Store everything uncompressed, so that the image can be patched
+#[derive(Copy, Clone)]
+struct Uncompressed;
+
+impl CompressionAction for Uncompressed {
+    fn decompress(
+        &self,
+        _bytes: &[u8],
+        _out: &mut Vec<u8>,
+        _compressor: Compressor,
+    ) -> Result<(), BackhandError> {
+        unreachable!("everything is stored uncompressed");
+    }
+
+    fn compress(
+        &self,
+        bytes: &[u8],
+        _fc: FilesystemCompressor,
+        _block_size: u32,
+    ) -> Result<Vec<u8>, BackhandError> {
+        // larger than the input, so the uncompressed bytes are stored instead
+        Ok(vec![0; bytes.len() + 1])
+    }
+}
+
+/// Offsets of the superblock fields patched by the tests, all little endian
+mod superblock {
+    pub const BLOCK_SIZE: usize = 12;
+    pub const FRAG_COUNT: usize = 16;
+    pub const COMPRESSOR: usize = 20;
+    pub const BLOCK_LOG: usize = 22;
+    pub const FLAGS: usize = 24;
+    pub const ID_COUNT: usize = 26;
+    pub const BYTES_USED: usize = 40;
+    pub const XATTR_TABLE: usize = 56;
+    pub const INODE_TABLE: usize = 64;
+    pub const DIR_TABLE: usize = 72;
+    pub const FRAG_TABLE: usize = 80;
+}
+
+/// Offsets of the fields of a directory entry: `offset: u16, inode_offset: i16, type: u16`, and
+/// `name_size: u16`, followed by the name. The first entry of a listing follows its header:
+/// `count: u32, start: u32, inode_num: u32`
+mod dir_entry {
+    pub const INODE_OFFSET: usize = 2;
+    pub const TYPE: usize = 4;
+    pub const SIZE: usize = 8;
+    pub const HEADER_INODE_NUM: usize = 8;
+    pub const HEADER_SIZE: usize = 12;
+}
+
+/// Write the image of a [`FilesystemWriter`] set up by `build`
+fn build_image<'a, 'b>(build: impl FnOnce(&mut FilesystemWriter<'a, 'b>)) -> Vec<u8> {
+    let mut fs = FilesystemWriter::default();
+    build(&mut fs);
+    let mut image = Cursor::new(vec![]);
+    fs.write(&mut image).unwrap();
+    image.into_inner()
+}
+
+fn read_image(image: Vec<u8>) -> FilesystemReader<'static> {
+    FilesystemReader::from_reader(Cursor::new(image)).unwrap()
+}
+
+/// Data of the file at `path`
+fn read_file(fs: &FilesystemReader, path: &str) -> Vec<u8> {
+    let node = fs.node_at_path(Path::new(path)).unwrap();
+    let InnerNode::File(file) = &node.inner else { panic!("{path} is not a file") };
+    let mut bytes = vec![];
+    fs.file_reader(file).read_to_end(&mut bytes).unwrap();
+    bytes
+}
+
+fn get_u16(image: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(image[offset..][..2].try_into().unwrap())
+}
+
+fn set_u16(image: &mut [u8], offset: usize, value: u16) {
+    image[offset..][..2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn get_u32(image: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(image[offset..][..4].try_into().unwrap())
+}
+
+fn set_u32(image: &mut [u8], offset: usize, value: u32) {
+    image[offset..][..4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn get_u64(image: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(image[offset..][..8].try_into().unwrap())
+}
+
+fn set_u64(image: &mut [u8], offset: usize, value: u64) {
+    image[offset..][..8].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Start of the directory entry named `name` in the directory table of an uncompressed image
+fn find_dir_entry(image: &[u8], name: &[u8]) -> usize {
+    let dir_table = get_u64(image, superblock::DIR_TABLE) as usize;
+    let pos = image[dir_table..].windows(name.len()).position(|w| w == name).unwrap();
+    dir_table + pos - dir_entry::SIZE
+}
+
+/// Same as `mksquashfs -no-fragments`: files not a multiple of the block size end in a partial
+/// block, instead of a fragment
+#[test]
+#[cfg(feature = "xz")]
+fn test_partial_final_block() {
+    let header = NodeHeader::default();
+    let block_size = MIN_BLOCK_SIZE as usize;
+
+    let compressible: Vec<u8> = (0..block_size * 3 + 123).map(|i| (i % 7) as u8).collect();
+    // xorshift, so the last block is stored uncompressed
+    let mut state = 0x2545_f491_u32;
+    let incompressible: Vec<u8> = (0..block_size + 1)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect();
+
+    let image = build_image(|fs| {
+        fs.set_block_size(MIN_BLOCK_SIZE);
+        fs.push_file(Cursor::new(compressible.clone()), "compressible", header).unwrap();
+        fs.push_file(Cursor::new(incompressible.clone()), "incompressible", header).unwrap();
+    });
+
+    let fs = read_image(image);
+    let (mut buf_read, mut buf_decompress) = fs.alloc_read_buffers();
+    for (path, expected, block_count) in
+        [("/compressible", &compressible, 4), ("/incompressible", &incompressible, 2)]
+    {
+        let node = fs.files().find(|node| node.fullpath == Path::new(path)).unwrap();
+        let InnerNode::File(file) = &node.inner else {
+            panic!("{path} is not a file");
+        };
+        assert_eq!(file.basic.frag_index, 0xffffffff);
+        assert_eq!(file.basic.block_sizes.len(), block_count);
+
+        let mut bytes = vec![];
+        fs.file(&file.basic)
+            .reader(&mut buf_read, &mut buf_decompress)
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(&bytes, expected);
+    }
+}
+
+/// A directory entry pointing back at the root directory isn't entered again
+#[test]
+fn test_dir_entry_pointing_at_root() {
+    let mut image = build_image(|fs| {
+        fs.set_kind(Kind::new(&Uncompressed));
+        fs.push_dir("loop", NodeHeader::default()).unwrap();
+    });
+
+    let squashfs = Squashfs::from_reader(Cursor::new(image.clone())).unwrap();
+    let root_inode_number = squashfs.root_inode.header.inode_number;
+
+    // the root directory only has this entry, so the entry directly follows the dir header
+    let entry = find_dir_entry(&image, b"loop");
+    let header = entry - dir_entry::HEADER_SIZE;
+    let header_inode_num = get_u32(&image, header + dir_entry::HEADER_INODE_NUM);
+    let inode_offset = (i64::from(root_inode_number) - i64::from(header_inode_num)) as i16;
+    set_u16(&mut image, entry + dir_entry::INODE_OFFSET, inode_offset as u16);
+
+    let fs = read_image(image);
+    assert_eq!(fs.len(), 1);
+}
+
+#[test]
+fn test_root_entries() {
+    let header = NodeHeader::default();
+    let image = build_image(|fs| {
+        fs.push_dir("a", header).unwrap();
+        fs.push_file(Cursor::new(vec![1]), "a/nested", header).unwrap();
+        fs.push_file(Cursor::new(vec![2]), "b", header).unwrap();
+    });
+
+    let fs = read_image(image);
+    assert_eq!(fs.root_node().fullpath, Path::new("/"));
+    assert!(matches!(fs.root_node().inner, InnerNode::Dir(_)));
+    let names: Vec<_> = fs.root_entries().map(|entry| entry.name.to_owned()).collect();
+    assert_eq!(names, ["a", "b"]);
+
+    let mut count = 0;
+    for node in &fs {
+        assert!(node.fullpath.starts_with("/"));
+        count += 1;
+    }
+    assert_eq!(count, fs.len());
+
+    assert!(fs.has_subtree(Path::new("/")));
+    assert!(fs.has_subtree(Path::new("/a")));
+    assert!(fs.has_subtree(Path::new("a/")));
+    assert!(!fs.has_subtree(Path::new("/b")));
+    assert!(!fs.has_subtree(Path::new("/a/nested")));
+    assert!(!fs.has_subtree(Path::new("/c")));
+}
+
+#[test]
+fn test_from_reader_locate() {
+    let header = NodeHeader::default();
+    let image = build_image(|fs| {
+        fs.push_file(Cursor::new(vec![1, 2, 3]), "file", header).unwrap();
+    });
+
+    // vendor header of variable length, containing a bogus magic
+    let mut bytes = b"VNDR\x00hsqs\x00\x00\x00".to_vec();
+    let image_offset = bytes.len() as u64;
+    bytes.extend_from_slice(&image);
+
+    let mut candidates = vec![];
+    let (squashfs, offset) = Squashfs::from_reader_locate(Cursor::new(bytes.clone()), |offset| {
+        candidates.push(offset);
+        bytes[..offset as usize].starts_with(b"VNDR") && offset >= 12
+    })
+    .unwrap();
+    assert_eq!(candidates, [5, image_offset]);
+    assert_eq!(offset, image_offset);
+    let fs = squashfs.into_filesystem_reader().unwrap();
+    assert!(fs.files().any(|node| node.fullpath == Path::new("/file")));
+
+    let ret = Squashfs::from_reader_locate(Cursor::new(bytes), |_| false);
+    assert!(matches!(ret, Err(BackhandError::SuperblockNotFound)));
+}
+
+#[test]
+fn test_content_fingerprint() {
+    fn image(kind: Option<Kind>, mod_time: u32, data: &[u8]) -> Vec<u8> {
+        build_image(|fs| {
+            if let Some(kind) = kind {
+                fs.set_kind(kind);
+            }
+            fs.set_time(mod_time);
+            fs.push_dir("dir", NodeHeader::default()).unwrap();
+            fs.push_file(Cursor::new(data.to_vec()), "dir/file", NodeHeader::default()).unwrap();
+            fs.push_symlink("dir/file", "link", NodeHeader::default()).unwrap();
+        })
+    }
+    fn hash(image: Vec<u8>) -> u64 {
+        let fs = read_image(image);
+        fs.content_fingerprint(&mut DefaultHasher::new()).unwrap()
+    }
+
+    let data = vec![0xaa; MIN_BLOCK_SIZE as usize * 3];
+    // same contents, but different compression and build time
+    let compressed = image(None, 1_700_000_000, &data);
+    let uncompressed = image(Some(Kind::new(&Uncompressed)), 1_800_000_000, &data);
+    assert_ne!(compressed, uncompressed);
+    let content = |image: &[u8]| {
+        let fs = read_image(image.to_vec());
+        let mut content = vec![];
+        fs.write_content(&mut content).unwrap();
+        content
+    };
+    assert_eq!(content(&compressed), content(&uncompressed));
+    assert!(content(&compressed).windows(data.len()).any(|window| window == data));
+    assert_eq!(hash(compressed.clone()), hash(uncompressed));
+
+    let mut other = data.clone();
+    other[1] = 0;
+    assert_ne!(hash(compressed), hash(image(None, 1_700_000_000, &other)));
+}
+
+#[test]
+fn test_read_dir_sorted() {
+    let header = |mtime| NodeHeader::new(0o755, 0, 0, mtime);
+    let image = build_image(|fs| {
+        fs.push_file(Cursor::new(vec![0; 30]), "a", header(3)).unwrap();
+        fs.push_dir("b", header(1)).unwrap();
+        fs.push_file(Cursor::new(vec![0; 10]), "c", header(2)).unwrap();
+    });
+
+    let fs = read_image(image);
+    for (order, expected) in [
+        (DirOrder::default(), ["a", "b", "c"]),
+        (DirOrder::NameDesc, ["c", "b", "a"]),
+        (DirOrder::SizeAsc, ["b", "c", "a"]),
+        (DirOrder::SizeDesc, ["a", "c", "b"]),
+        (DirOrder::MtimeAsc, ["b", "c", "a"]),
+        (DirOrder::DirsFirst, ["b", "a", "c"]),
+    ] {
+        let names: Vec<_> =
+            fs.read_dir_sorted("/", order).unwrap().iter().map(|entry| entry.name).collect();
+        assert_eq!(names, expected, "{order:?}");
+    }
+}
+
+#[test]
+#[cfg(feature = "tar")]
+fn test_to_tar() {
+    let header = NodeHeader::new(0o640, 1000, 100, 1_700_000_000);
+    let image = build_image(|fs| {
+        fs.push_dir_all("usr/lib", header).unwrap();
+        fs.push_file(Cursor::new(vec![0xab; MIN_BLOCK_SIZE as usize + 7]), "usr/lib/data", header)
+            .unwrap();
+        fs.push_symlink("lib/data", "usr/link", header).unwrap();
+        fs.push_file(Cursor::new(vec![1]), "outside", header).unwrap();
+    });
+
+    let fs = read_image(image);
+    let mut out = vec![];
+    fs.to_tar(Path::new("/usr"), &mut out).unwrap();
+
+    let mut archive = tar::Archive::new(Cursor::new(out));
+    let mut entries = vec![];
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let header = entry.header().clone();
+        assert_eq!(header.mode().unwrap(), 0o640);
+        assert_eq!(header.uid().unwrap(), 1000);
+        assert_eq!(header.gid().unwrap(), 100);
+        assert_eq!(header.mtime().unwrap(), 1_700_000_000);
+        let mut data = vec![];
+        entry.read_to_end(&mut data).unwrap();
+        let link = entry.link_name().unwrap().map(|link| link.into_owned());
+        entries.push((entry.path().unwrap().into_owned(), header.entry_type(), data, link));
+    }
+
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].0, Path::new("lib"));
+    assert!(entries[0].1.is_dir());
+    assert_eq!(entries[1].0, Path::new("lib/data"));
+    assert!(entries[1].1.is_file());
+    assert_eq!(entries[1].2, vec![0xab; MIN_BLOCK_SIZE as usize + 7]);
+    assert_eq!(entries[2].0, Path::new("link"));
+    assert!(entries[2].1.is_symlink());
+    assert_eq!(entries[2].3.as_deref(), Some(Path::new("lib/data")));
+
+    // anything but a directory is a single entry
+    let mut out = vec![];
+    fs.to_tar(Path::new("/usr/lib/data"), &mut out).unwrap();
+    let mut archive = tar::Archive::new(Cursor::new(out));
+    let mut entries = archive.entries().unwrap();
+    let mut entry = entries.next().unwrap().unwrap();
+    assert_eq!(entry.path().unwrap(), Path::new("data"));
+    assert!(entry.header().entry_type().is_file());
+    let mut data = vec![];
+    entry.read_to_end(&mut data).unwrap();
+    assert_eq!(data, vec![0xab; MIN_BLOCK_SIZE as usize + 7]);
+    assert!(entries.next().is_none());
+}
+
+#[test]
+fn test_zero_id_count() {
+    let mut image = build_image(|fs| {
+        fs.push_file(Cursor::new(vec![1]), "file", NodeHeader::new(0o644, 0, 0, 0)).unwrap();
+    });
+
+    set_u16(&mut image, superblock::ID_COUNT, 0);
+
+    let squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    assert_eq!(squashfs.superblock.id_count, 0);
+    assert!(squashfs.id.is_empty());
+    assert_eq!(squashfs.warnings(), [ReadWarning::EmptyIdTable]);
+    let fs = squashfs.into_filesystem_reader().unwrap();
+    let node = fs.files().find(|node| node.fullpath == Path::new("/file")).unwrap();
+    assert_eq!((node.header.uid, node.header.gid), (0, 0));
+}
+
+#[test]
+#[cfg(feature = "gzip")]
+fn test_from_path_auto_gzip() {
+    let image = build_image(|fs| {
+        fs.push_file(Cursor::new(b"wrapped".to_vec()), "file", NodeHeader::default()).unwrap();
+    });
+
+    let mut compressor = libdeflater::Compressor::new(libdeflater::CompressionLvl::default());
+    let mut gzip = vec![0; compressor.gzip_compress_bound(image.len())];
+    let len = compressor.gzip_compress(&image, &mut gzip).unwrap();
+    gzip.truncate(len);
+
+    let dir = tempfile::tempdir().unwrap();
+    for (name, bytes) in [("image.squashfs", &image), ("image.squashfs.gz", &gzip)] {
+        let path = dir.path().join(name);
+        std::fs::write(&path, bytes).unwrap();
+        let squashfs = Squashfs::from_path_auto(&path).unwrap();
+        let fs = squashfs.into_filesystem_reader().unwrap();
+        assert!(fs.files().any(|node| node.fullpath == Path::new("/file")), "{name}");
+    }
+}
+
+/// Same as `mksquashfs -noD`: the superblock flag is informational, each block decides if it's
+/// compressed
+#[test]
+fn test_data_block_stored_uncompressed() {
+    const DATA_AND_FRAGMENTS_UNCOMPRESSED: u16 = 0b0000_0000_0000_1010;
+    let data: Vec<u8> = (0..MIN_BLOCK_SIZE as usize * 3 + 123).map(|i| (i % 7) as u8).collect();
+
+    for kind in [Some(Kind::new(&Uncompressed)), None] {
+        let mut image = build_image(|fs| {
+            fs.set_block_size(MIN_BLOCK_SIZE);
+            if let Some(kind) = kind {
+                fs.set_kind(kind);
+            }
+            fs.push_file(Cursor::new(data.clone()), "file", NodeHeader::default()).unwrap();
+        });
+
+        let flags = get_u16(&image, superblock::FLAGS);
+        set_u16(&mut image, superblock::FLAGS, flags | DATA_AND_FRAGMENTS_UNCOMPRESSED);
+
+        let fs = read_image(image);
+        assert_eq!(read_file(&fs, "/file"), data);
+    }
+}
+
+#[test]
+fn test_iter_with_inodes() {
+    let data = vec![1; MIN_BLOCK_SIZE as usize * 2 + 1];
+    let image = build_image(|fs| {
+        fs.set_block_size(MIN_BLOCK_SIZE);
+        fs.push_dir("dir", NodeHeader::default()).unwrap();
+        fs.push_file(Cursor::new(data), "dir/file", NodeHeader::default()).unwrap();
+    });
+
+    let fs = read_image(image);
+    let paths: Vec<_> = fs.iter_with_inodes().map(|(path, _)| path).collect();
+    assert_eq!(paths, [Path::new("/"), Path::new("/dir"), Path::new("/dir/file")]);
+    for (path, inode) in fs.iter_with_inodes() {
+        match &inode.inner {
+            InodeInner::BasicDirectory(_) | InodeInner::ExtendedDirectory(_) => {
+                assert_ne!(path, Path::new("/dir/file"))
+            }
+            InodeInner::BasicFile(file) => {
+                assert_eq!(path, Path::new("/dir/file"));
+                assert_eq!(file.block_sizes.len(), 2);
+            }
+            inner => panic!("unexpected inode {inner:?}"),
+        }
+    }
+}
+
+#[test]
+fn test_inflated_table_count() {
+    let image = build_image(|fs| {
+        // small enough to be stored in a fragment
+        fs.push_file(Cursor::new(vec![1; 10]), "file", NodeHeader::default()).unwrap();
+    });
+    Squashfs::from_reader(Cursor::new(image.clone())).unwrap();
+
+    let mut inflated = image;
+    set_u32(&mut inflated, superblock::FRAG_COUNT, u32::MAX);
+    let ret = Squashfs::from_reader(Cursor::new(inflated));
+    assert!(matches!(ret, Err(BackhandError::TableTooLarge)));
+}
+
+#[test]
+fn test_zero_inode_count() {
+    let mut image = Cursor::new(build_image(|_| {}));
+
+    // remove all inodes, by making the inode table empty
+    let mut squashfs = Squashfs::from_reader(Cursor::new(image.get_ref().clone())).unwrap();
+    let superblock = squashfs.superblock_mut();
+    superblock.inode_count = 0;
+    superblock.inode_table = superblock.dir_table;
+    squashfs.write_superblock(&mut image).unwrap();
+
+    let squashfs = Squashfs::from_reader(Cursor::new(image.into_inner())).unwrap();
+    assert!(squashfs.inodes.is_empty());
+    let fs = squashfs.into_filesystem_reader().unwrap();
+    assert_eq!(fs.len(), 1);
+    assert!(matches!(fs.root_node().inner, InnerNode::Dir(_)));
+    assert_eq!(fs.read_dir("/").unwrap().count(), 0);
+}
+
+#[test]
+fn test_directory_index() {
+    let image = build_image(|fs| {
+        fs.push_file(Cursor::new(vec![1]), "file", NodeHeader::default()).unwrap();
+    });
+
+    let squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    let index = squashfs.directory_index(&squashfs.root_inode).unwrap();
+    if let InodeInner::BasicDirectory(_) = squashfs.root_inode.inner {
+        assert!(index.is_empty());
+    }
+    let file = squashfs
+        .inodes
+        .values()
+        .find(|inode| matches!(inode.inner, InodeInner::BasicFile(_)))
+        .unwrap();
+    assert!(matches!(squashfs.directory_index(file), Err(BackhandError::UnexpectedInode(_))));
+}
+
+#[test]
+fn test_filesystem_reader_shared() {
+    let image = build_image(|fs| {
+        fs.push_file(Cursor::new(b"shared".to_vec()), "file", NodeHeader::default()).unwrap();
+    });
+
+    let squashfs = Squashfs::from_reader(Cursor::new(image.clone())).unwrap();
+    let fs = squashfs.filesystem_reader(Cursor::new(image)).unwrap();
+    // both views are still usable
+    assert!(!squashfs.inodes.is_empty());
+    assert_eq!(read_file(&fs, "/file"), b"shared");
+}
+
+#[test]
+fn test_compressor_none_with_options_flag() {
+    const COMPRESSOR_OPTIONS_ARE_PRESENT: u16 = 0b0000_0100_0000_0000;
+    let mut image = build_image(|fs| {
+        fs.set_kind(Kind::new(&Uncompressed));
+        fs.push_file(Cursor::new(b"data".to_vec()), "file", NodeHeader::default()).unwrap();
+    });
+
+    set_u16(&mut image, superblock::COMPRESSOR, Compressor::None as u16);
+    let flags = get_u16(&image, superblock::FLAGS);
+    set_u16(&mut image, superblock::FLAGS, flags | COMPRESSOR_OPTIONS_ARE_PRESENT);
+
+    // the flag is ignored, and nothing is parsed as compression options
+    let squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    assert_eq!(squashfs.superblock.compressor, Compressor::None);
+    assert!(squashfs.superblock.compressor_options_are_present());
+    assert_eq!(squashfs.compression_options, None);
+    assert_eq!(squashfs.warnings(), [ReadWarning::CompressionOptionsWithoutCompressor]);
+    let fs = squashfs.into_filesystem_reader().unwrap();
+    assert!(fs.files().any(|node| node.fullpath == Path::new("/file")));
+}
+
+#[test]
+fn test_read_to_string() {
+    let header = NodeHeader::default();
+    let image = build_image(|fs| {
+        fs.push_dir("etc", header).unwrap();
+        fs.push_file(Cursor::new(b"NAME=test\n".to_vec()), "etc/os-release", header).unwrap();
+        fs.push_file(Cursor::new(vec![b'a', 0xff, 0xfe]), "etc/binary", header).unwrap();
+        fs.push_symlink("etc/os-release", "os-release", header).unwrap();
+    });
+
+    let fs = read_image(image);
+    assert_eq!(fs.read_to_string("/etc/os-release").unwrap(), "NAME=test\n");
+    assert_eq!(fs.read_to_string("os-release").unwrap(), "NAME=test\n");
+    match fs.read_to_string("/etc/binary") {
+        Err(BackhandError::StringUtf8(e)) => assert_eq!(e.utf8_error().valid_up_to(), 1),
+        ret => panic!("unexpected {ret:?}"),
+    }
+    assert!(matches!(fs.read_to_string("/etc"), Err(BackhandError::NotAFile)));
+    assert!(matches!(fs.read_to_string("/missing"), Err(BackhandError::FileNotFound)));
+}
+
+/// Move the fragment block to directly after the dir table, before the fragment table
+#[test]
+fn test_fragment_after_dir_table() {
+    let data = b"stored in a fragment".to_vec();
+    let image = build_image(|fs| {
+        fs.set_kind(Kind::new(&Uncompressed));
+        fs.push_dir("dir", NodeHeader::default()).unwrap();
+        fs.push_file(Cursor::new(data.clone()), "dir/file", NodeHeader::default()).unwrap();
+    });
+
+    let mut squashfs = Squashfs::from_reader(Cursor::new(image.clone())).unwrap();
+    let fragment = squashfs.fragments.as_ref().unwrap()[0];
+    assert!(!fragment.is_compressed());
+    let fragment_start = fragment.start as usize;
+    let fragment_bytes = image[fragment_start..][..fragment.block_size() as usize].to_vec();
+
+    // insert the fragment block before the metadata of the fragment table
+    let superblock = squashfs.superblock_mut();
+    let insert_at = get_u64(&image, superblock.frag_table as usize);
+    let shift = fragment_bytes.len() as u64;
+    let mut moved = image[..insert_at as usize].to_vec();
+    moved.extend_from_slice(&fragment_bytes);
+    moved.extend_from_slice(&image[insert_at as usize..]);
+
+    // fix all pointers after the insertion
+    superblock.bytes_used += shift;
+    superblock.frag_table += shift;
+    superblock.id_table += shift;
+    for table in [superblock.frag_table, superblock.id_table] {
+        let ptr = get_u64(&moved, table as usize);
+        set_u64(&mut moved, table as usize, ptr + shift);
+    }
+    // uncompressed metadata block, the fragment entry starts after the header
+    set_u64(&mut moved, (insert_at + shift) as usize + 2, insert_at);
+    // and erase the original
+    moved[fragment_start..][..fragment_bytes.len()].fill(0);
+    let mut moved = Cursor::new(moved);
+    squashfs.write_superblock(&mut moved).unwrap();
+
+    let squashfs = Squashfs::from_reader(Cursor::new(moved.into_inner())).unwrap();
+    assert_eq!(squashfs.fragments.as_ref().unwrap()[0].start, insert_at);
+    let fs = squashfs.into_filesystem_reader().unwrap();
+    assert_eq!(read_file(&fs, "/dir/file"), data);
+}
+
+#[test]
+fn test_table_block_counts() {
+    let image = build_image(|fs| {
+        fs.push_file(Cursor::new(vec![1; 10]), "file", NodeHeader::default()).unwrap();
+    });
+
+    let squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    let counts = squashfs.table_block_counts();
+    assert_eq!(counts.inode, 1);
+    assert_eq!(counts.dir, 1);
+    assert_eq!(counts.fragment, 1);
+    assert_eq!(counts.export, 0);
+    assert_eq!(counts.id, 1);
+}
+
+#[test]
+fn test_dir_entry_negative_inode_offset() {
+    let image = build_image(|fs| {
+        fs.set_kind(Kind::new(&Uncompressed));
+        fs.push_file(Cursor::new(b"data".to_vec()), "negative", NodeHeader::default()).unwrap();
+    });
+    let inode_count =
+        Squashfs::from_reader(Cursor::new(image.clone())).unwrap().superblock.inode_count;
+
+    // the root directory only has this entry, so the entry directly follows the dir header
+    let entry = find_dir_entry(&image, b"negative");
+    let header_inode_num_at = entry - dir_entry::HEADER_SIZE + dir_entry::HEADER_INODE_NUM;
+    let inode_num = get_u32(&image, header_inode_num_at);
+    let patch = |header_inode_num: u32, inode_offset: i16| {
+        let mut image = image.clone();
+        set_u32(&mut image, header_inode_num_at, header_inode_num);
+        set_u16(&mut image, entry + dir_entry::INODE_OFFSET, inode_offset as u16);
+        FilesystemReader::from_reader(Cursor::new(image))
+    };
+
+    // same inode, referenced from a larger header inode number
+    let fs = patch(inode_num + 5, -5).unwrap();
+    assert!(fs.files().any(|node| node.fullpath == Path::new("/negative")));
+
+    // resolving to 0, and past inode_count
+    let ret = patch(inode_num, -(inode_num as i16));
+    assert!(matches!(ret, Err(BackhandError::InvalidInodeNumber(0))));
+    let ret = patch(inode_count, 1);
+    assert!(
+        matches!(ret, Err(BackhandError::InvalidInodeNumber(n)) if n == i64::from(inode_count) + 1)
+    );
+}
+
+#[test]
+fn test_symlink_target_node() {
+    let header = NodeHeader::default();
+    let image = build_image(|fs| {
+        fs.push_dir_all("usr/lib", header).unwrap();
+        fs.push_file(Cursor::new(vec![1]), "usr/lib/libc.so.6", header).unwrap();
+        fs.push_symlink("libc.so.6", "usr/lib/libc.so", header).unwrap();
+        fs.push_symlink("usr/lib", "lib", header).unwrap();
+        // chain of symlinks, through the symlinked dir
+        fs.push_symlink("/lib/libc.so", "chain", header).unwrap();
+        fs.push_symlink("missing", "dangling", header).unwrap();
+    });
+
+    let fs = read_image(image);
+    let target = fs.symlink_target_node("/usr/lib/libc.so").unwrap();
+    assert_eq!(target.fullpath, Path::new("/usr/lib/libc.so.6"));
+    let target = fs.symlink_target_node("/lib/libc.so").unwrap();
+    assert_eq!(target.fullpath, Path::new("/usr/lib/libc.so.6"));
+    // only one hop
+    let target = fs.symlink_target_node("/chain").unwrap();
+    assert_eq!(target.fullpath, Path::new("/usr/lib/libc.so"));
+    assert!(matches!(target.inner, InnerNode::Symlink(_)));
+    let target = fs.symlink_target_node("/lib").unwrap();
+    assert_eq!(target.fullpath, Path::new("/usr/lib"));
+
+    assert!(matches!(fs.symlink_target_node("/usr"), Err(BackhandError::NotASymlink)));
+    assert!(matches!(fs.symlink_target_node("/dangling"), Err(BackhandError::DanglingSymlink)));
+    assert!(matches!(fs.symlink_target_node("/missing"), Err(BackhandError::FileNotFound)));
+}
+
+#[test]
+fn test_node_mtime() {
+    let image = build_image(|fs| {
+        fs.set_time(1_000);
+        fs.push_file(Cursor::new(vec![1]), "old", NodeHeader::new(0o644, 0, 0, 10)).unwrap();
+        fs.push_file(Cursor::new(vec![2]), "new", NodeHeader::new(0o644, 0, 0, 20)).unwrap();
+    });
+
+    let fs = read_image(image);
+    let old = fs.files().find(|node| node.fullpath == Path::new("/old")).unwrap();
+    let new = fs.files().find(|node| node.fullpath == Path::new("/new")).unwrap();
+    assert_eq!(old.header.mtime(), 10);
+    assert_eq!(new.header.mtime(), 20);
+    assert_eq!(new.header.modified_time(), UNIX_EPOCH + Duration::from_secs(20));
+    assert_ne!(old.header.modified_time(), new.header.modified_time());
+    assert_eq!(fs.mod_time, 1_000);
+}
+
+#[test]
+fn test_block_log_consistent() {
+    let image = build_image(|fs| {
+        fs.push_file(Cursor::new(vec![1]), "file", NodeHeader::default()).unwrap();
+    });
+
+    let squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    let mut superblock = squashfs.superblock;
+    assert!(superblock.block_log_consistent());
+    superblock.block_log += 1;
+    assert!(!superblock.block_log_consistent());
+    superblock.block_log = 40;
+    assert!(!superblock.block_log_consistent());
+}
+
+#[test]
+#[cfg(feature = "memmap")]
+fn test_extract_file_mmap() {
+    let data: Vec<u8> = (0..MIN_BLOCK_SIZE as usize * 2 + 100).map(|i| i as u8).collect();
+    let image = build_image(|fs| {
+        fs.set_block_size(MIN_BLOCK_SIZE);
+        fs.push_file(Cursor::new(data.clone()), "large", NodeHeader::default()).unwrap();
+        fs.push_file(Cursor::new(vec![]), "empty", NodeHeader::default()).unwrap();
+        fs.push_dir("dir", NodeHeader::default()).unwrap();
+    });
+
+    let fs = read_image(image);
+    let tmp = tempfile::tempdir().unwrap();
+    let node = |path: &str| fs.files().find(|node| node.fullpath == Path::new(path)).unwrap();
+
+    let dest = tmp.path().join("large");
+    fs.extract_file_mmap(node("/large"), &dest).unwrap();
+    assert_eq!(std::fs::read(&dest).unwrap(), data);
+
+    let dest = tmp.path().join("empty");
+    fs.extract_file_mmap(node("/empty"), &dest).unwrap();
+    assert_eq!(std::fs::read(&dest).unwrap(), Vec::<u8>::new());
+
+    let ret = fs.extract_file_mmap(node("/dir"), &tmp.path().join("dir"));
+    assert!(matches!(ret, Err(BackhandError::NotAFile)));
+}
+
+#[test]
+fn test_trailing_data() {
+    let image = build_image(|fs| {
+        fs.push_file(Cursor::new(vec![1; 100]), "file", NodeHeader::default()).unwrap();
+    });
+
+    let mut squashfs = Squashfs::from_reader(Cursor::new(image.clone())).unwrap();
+    assert!(squashfs.trailing_data().unwrap().is_empty());
+
+    let mut signed = image.clone();
+    signed.extend_from_slice(b"signature");
+    let mut squashfs = Squashfs::from_reader(Cursor::new(signed)).unwrap();
+    assert_eq!(squashfs.trailing_data().unwrap(), b"signature");
+
+    // relative to the image, not the start of the file
+    let mut prefixed = vec![0; 0x100];
+    prefixed.extend_from_slice(&image);
+    prefixed.extend_from_slice(b"signature");
+    let mut squashfs = Squashfs::from_reader_with_offset(Cursor::new(prefixed), 0x100).unwrap();
+    assert_eq!(squashfs.trailing_data().unwrap(), b"signature");
+}
+
+#[test]
+fn test_walk_order() {
+    let header = NodeHeader::default();
+    let image = build_image(|fs| {
+        fs.push_dir_all("a/b", header).unwrap();
+        fs.push_file(Cursor::new(vec![1]), "a/b/c", header).unwrap();
+        fs.push_file(Cursor::new(vec![1]), "a/d", header).unwrap();
+        fs.push_dir("a-e", header).unwrap();
+        fs.push_file(Cursor::new(vec![1]), "a-e/f", header).unwrap();
+        fs.push_file(Cursor::new(vec![1]), "g", header).unwrap();
+    });
+
+    let fs = read_image(image);
+    let paths = |walk: Vec<&backhand::Node<_>>| {
+        walk.iter().map(|node| node.fullpath.to_str().unwrap().to_string()).collect::<Vec<_>>()
+    };
+    assert_eq!(
+        paths(fs.walk_dfs_pre().collect()),
+        ["/", "/a", "/a/b", "/a/b/c", "/a/d", "/a-e", "/a-e/f", "/g"]
+    );
+    assert_eq!(
+        paths(fs.walk_dfs_post().collect()),
+        ["/a/b/c", "/a/b", "/a/d", "/a", "/a-e/f", "/a-e", "/g", "/"]
+    );
+    assert_eq!(
+        paths(fs.walk_bfs().collect()),
+        ["/", "/a", "/a-e", "/g", "/a/b", "/a/d", "/a-e/f", "/a/b/c"]
+    );
+}
+
+#[test]
+fn test_oversized_metadata_block() {
+    /// Decompress every block into one byte too many
+    #[derive(Copy, Clone)]
+    struct Oversized;
+
+    impl CompressionAction for Oversized {
+        fn decompress(
+            &self,
+            _bytes: &[u8],
+            out: &mut Vec<u8>,
+            _compressor: Compressor,
+        ) -> Result<(), BackhandError> {
+            out.resize(METADATA_MAXSIZE + 1, 0);
+            Ok(())
+        }
+
+        fn compress(
+            &self,
+            bytes: &[u8],
+            _fc: FilesystemCompressor,
+            _block_size: u32,
+        ) -> Result<Vec<u8>, BackhandError> {
+            Ok(bytes.to_vec())
+        }
+    }
+
+    // compressed block of 4 bytes
+    let block = [0x04, 0x00, 0xaa, 0xbb, 0xcc, 0xdd];
+    let kind = Kind::new(&Oversized);
+    let ret = read_block_with_compressor(&mut Cursor::new(block), &kind, Compressor::Xz);
+    assert!(
+        matches!(ret, Err(BackhandError::OversizedMetadataBlock(len)) if len == METADATA_MAXSIZE + 1)
+    );
+
+    // uncompressed block, one byte too long
+    let mut block = (set_if_uncompressed(METADATA_MAXSIZE as u16 + 1)).to_le_bytes().to_vec();
+    block.resize(2 + METADATA_MAXSIZE + 1, 0);
+    let ret = read_block_with_compressor(&mut Cursor::new(block), &kind, Compressor::Xz);
+    assert!(matches!(ret, Err(BackhandError::OversizedMetadataBlock(_))));
+
+    // largest valid block
+    let mut block = (set_if_uncompressed(METADATA_MAXSIZE as u16)).to_le_bytes().to_vec();
+    block.resize(2 + METADATA_MAXSIZE, 0);
+    let ret = read_block_with_compressor(&mut Cursor::new(block), &kind, Compressor::Xz);
+    assert_eq!(ret.unwrap().len(), METADATA_MAXSIZE);
+}
+
+#[test]
+fn test_du() {
+    let header = NodeHeader::default();
+    let block_size = MIN_BLOCK_SIZE as usize;
+    let image = build_image(|fs| {
+        fs.set_block_size(MIN_BLOCK_SIZE);
+        fs.push_dir_all("usr/lib", header).unwrap();
+        fs.push_file(Cursor::new(vec![0; block_size * 2 + 10]), "usr/lib/zeros", header).unwrap();
+        fs.push_file(Cursor::new(vec![1; 100]), "usr/small", header).unwrap();
+        fs.push_symlink("usr", "link", header).unwrap();
+        fs.push_file(Cursor::new(vec![2; 5]), "other", header).unwrap();
+    });
+
+    let fs = read_image(image);
+    assert_eq!(fs.du("/").unwrap(), (block_size * 2 + 10 + 100 + 5) as u64);
+    assert_eq!(fs.du("/usr").unwrap(), (block_size * 2 + 10 + 100) as u64);
+    assert_eq!(fs.du("/usr/small").unwrap(), 100);
+    // not followed
+    assert_eq!(fs.du("/link").unwrap(), 0);
+    assert!(matches!(fs.du("/missing"), Err(BackhandError::FileNotFound)));
+
+    // the zero blocks compress well, the 10 bytes in the fragment are counted as is
+    let on_disk = fs.du_on_disk("/usr/lib").unwrap();
+    assert!(on_disk > 10 && on_disk < block_size as u64, "{on_disk}");
+    assert_eq!(fs.du_on_disk("/usr/small").unwrap(), 100);
+}
+
+#[test]
+fn test_root_outside_dir_table() {
+    let image = build_image(|fs| {
+        fs.push_file(Cursor::new(vec![1; 100]), "file", NodeHeader::default()).unwrap();
+    });
+
+    // point the root listing at the fragment table, after all directory table blocks
+    let mut squashfs = Squashfs::from_reader(Cursor::new(image.clone())).unwrap();
+    let past_dir_table =
+        u32::try_from(squashfs.superblock.frag_table - squashfs.superblock.dir_table).unwrap();
+    match &mut squashfs.root_inode.inner {
+        InodeInner::BasicDirectory(dir) => dir.block_index = past_dir_table,
+        InodeInner::ExtendedDirectory(dir) => dir.block_index = past_dir_table,
+        _ => panic!("root is not a dir"),
+    }
+    let ret = squashfs.into_filesystem_reader();
+    assert!(matches!(ret, Err(BackhandError::DirectoryNotInDirTable)));
+
+    // inside the first block, but not at its start
+    let mut squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    match &mut squashfs.root_inode.inner {
+        InodeInner::BasicDirectory(dir) => dir.block_index = 1,
+        InodeInner::ExtendedDirectory(dir) => dir.block_index = 1,
+        _ => panic!("root is not a dir"),
+    }
+    let ret = squashfs.into_filesystem_reader();
+    assert!(matches!(ret, Err(BackhandError::DirectoryNotInDirTable)));
+}
+
+#[test]
+fn test_read_ahead() {
+    let header = NodeHeader::default();
+    let block_size = MIN_BLOCK_SIZE as usize;
+    let mut expected = vec![];
+    let image = build_image(|fs| {
+        fs.set_block_size(MIN_BLOCK_SIZE);
+        for i in 0..8 {
+            let data: Vec<u8> = (0..block_size * i + i * 100).map(|b| (b * i) as u8).collect();
+            let name = format!("file{i}");
+            fs.push_file(Cursor::new(data.clone()), &name, header).unwrap();
+            expected.push((format!("/{name}"), data));
+        }
+    });
+
+    // smaller than a block, and larger than the image
+    for size in [100, block_size * 4, image.len() * 2] {
+        let fs = read_image(image.clone()).with_read_ahead(size).unwrap();
+        for (path, data) in &expected {
+            assert_eq!(&read_file(&fs, path), data, "{path} with read ahead of {size}");
+        }
+    }
+
+    let ret = read_image(image).with_read_ahead(0).err();
+    assert!(
+        matches!(&ret, Some(BackhandError::StdIo(e)) if e.kind() == std::io::ErrorKind::InvalidInput),
+        "{ret:?}"
+    );
+}
+
+#[test]
+#[cfg(feature = "gzip")]
+fn test_compression_options_raw() {
+    use backhand::compression::{CompressionOptions, Gzip};
+
+    let options = Gzip { compression_level: 9, window_size: 15, strategies: 0 };
+    let image = build_image(|fs| {
+        fs.set_compressor(
+            FilesystemCompressor::new(Compressor::Gzip, Some(CompressionOptions::Gzip(options)))
+                .unwrap(),
+        );
+        fs.push_file(Cursor::new(vec![1; 100]), "file", NodeHeader::default()).unwrap();
+    });
+
+    let squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    assert_eq!(squashfs.compression_options_raw(), Some(&[9, 0, 0, 0, 15, 0, 0, 0][..]));
+
+    // no options stored
+    let image = build_image(|fs| {
+        fs.set_compressor(FilesystemCompressor::new(Compressor::Gzip, None).unwrap());
+    });
+    let squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    assert_eq!(squashfs.compression_options_raw(), None);
+}
+
+#[test]
+fn test_is_empty_dir() {
+    let header = NodeHeader::default();
+    let image = build_image(|fs| {
+        fs.push_dir_all("full/empty", header).unwrap();
+        fs.push_dir("other", header).unwrap();
+        fs.push_file(Cursor::new(vec![1]), "other/file", header).unwrap();
+    });
+
+    let fs = read_image(image);
+    assert!(!fs.is_empty_dir("/").unwrap());
+    assert!(!fs.is_empty_dir("/full").unwrap());
+    assert!(fs.is_empty_dir("/full/empty").unwrap());
+    assert!(!fs.is_empty_dir("other").unwrap());
+    assert!(matches!(fs.is_empty_dir("/other/file"), Err(BackhandError::NotADirectory)));
+    assert!(matches!(fs.is_empty_dir("/missing"), Err(BackhandError::FileNotFound)));
+}
+
+/// Block list of the inode spanning multiple metadata blocks
+#[test]
+fn test_block_list_across_metadata_blocks() {
+    const BLOCKS: usize = 3000;
+    let block_size = MIN_BLOCK_SIZE as usize;
+    let data: Vec<u8> = (0..block_size * BLOCKS + 10).map(|i| (i / block_size + i) as u8).collect();
+
+    let image = build_image(|fs| {
+        fs.set_kind(Kind::new(&Uncompressed));
+        fs.set_block_size(MIN_BLOCK_SIZE);
+        fs.push_file(Cursor::new(vec![1; 10]), "before", NodeHeader::default()).unwrap();
+        fs.push_file(Cursor::new(data.clone()), "large", NodeHeader::default()).unwrap();
+    });
+
+    let fs = read_image(image.clone());
+    let (_, inode) = fs.iter_with_inodes().find(|(path, _)| *path == Path::new("/large")).unwrap();
+    let InodeInner::BasicFile(file) = &inode.inner else { panic!("not a basic file") };
+    assert_eq!(file.block_sizes.len(), BLOCKS);
+    assert!(file.block_sizes.len() * 4 > METADATA_MAXSIZE);
+    let inode_number = inode.header.inode_number;
+    assert!(read_file(&fs, "/large") == data);
+
+    // only reading the metadata blocks containing the inode
+    let mut squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    let raw = squashfs.raw_inode_bytes(inode_number).unwrap();
+    // header, basic file fields, and the block list
+    assert_eq!(raw.len(), 16 + 16 + BLOCKS * 4);
+}
+
+#[test]
+fn test_extract_matching() {
+    let header = NodeHeader::new(0o644, 0, 0, 0);
+    let fs = read_image(build_image(|fs| {
+        fs.push_dir_all("lib/modules/6.1/kernel/net", header).unwrap();
+        fs.push_file(Cursor::new(vec![1; 10]), "lib/modules/6.1/kernel/net/tun.ko", header)
+            .unwrap();
+        fs.push_file(Cursor::new(vec![2; 20]), "lib/modules/6.1/loop.ko", header).unwrap();
+        fs.push_file(Cursor::new(vec![3; 30]), "lib/modules/6.1/modules.dep", header).unwrap();
+        fs.push_file(Cursor::new(vec![4; 40]), "other.ko", header).unwrap();
+    }));
+
+    // file names only
+    let tmp = tempfile::tempdir().unwrap();
+    let report = fs.extract_matching("*.ko", tmp.path(), ExtractOptions::default()).unwrap();
+    assert_eq!(report.files, 3);
+    assert_eq!(report.bytes_written, 70);
+    assert_eq!(report.dirs, 0);
+    assert_eq!(
+        std::fs::read(tmp.path().join("lib/modules/6.1/kernel/net/tun.ko")).unwrap(),
+        [1; 10]
+    );
+    assert!(tmp.path().join("other.ko").is_file());
+    assert!(!tmp.path().join("lib/modules/6.1/modules.dep").exists());
+
+    // whole path
+    let tmp = tempfile::tempdir().unwrap();
+    let report =
+        fs.extract_matching("/lib/**/*.ko", tmp.path(), ExtractOptions::default()).unwrap();
+    assert_eq!(report.files, 2);
+    assert_eq!(report.bytes_written, 30);
+    assert!(tmp.path().join("lib/modules/6.1/loop.ko").is_file());
+    assert!(!tmp.path().join("other.ko").exists());
+
+    let tmp = tempfile::tempdir().unwrap();
+    let report =
+        fs.extract_matching("lib/modules/?.?/*", tmp.path(), ExtractOptions::default()).unwrap();
+    assert_eq!(report.files, 2);
+    assert_eq!(report.dirs, 1);
+    assert!(tmp.path().join("lib/modules/6.1/kernel").is_dir());
+    assert!(!tmp.path().join("lib/modules/6.1/kernel/net").exists());
+
+    let tmp = tempfile::tempdir().unwrap();
+    let report = fs
+        .extract_matching("/**/6.1/**/net/**/*.k?", tmp.path(), ExtractOptions::default())
+        .unwrap();
+    assert_eq!(report.files, 1);
+    assert!(tmp.path().join("lib/modules/6.1/kernel/net/tun.ko").is_file());
+}
+
+#[test]
+fn test_extract_matching_many_wildcards() {
+    let name = "a".repeat(200);
+    let fs = read_image(build_image(|fs| {
+        fs.push_file(Cursor::new(vec![1]), &name, NodeHeader::default()).unwrap();
+    }));
+
+    // exponential with backtracking to every wildcard
+    let pattern = format!("{}b", "*a".repeat(20));
+    let tmp = tempfile::tempdir().unwrap();
+    let report = fs.extract_matching(&pattern, tmp.path(), ExtractOptions::default()).unwrap();
+    assert_eq!(report.files, 0);
+
+    let pattern = format!("{}*", "*a".repeat(20));
+    let report = fs.extract_matching(&pattern, tmp.path(), ExtractOptions::default()).unwrap();
+    assert_eq!(report.files, 1);
+    assert!(tmp.path().join(&name).is_file());
+}
+
+#[test]
+fn test_block_sizes() {
+    let block_size = MIN_BLOCK_SIZE as usize;
+    // compressible block, then an incompressible one
+    let mut data = vec![0; block_size];
+    let mut state = 0x2545_f491_u32;
+    data.extend((0..block_size).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as u8
+    }));
+    data.extend_from_slice(&[1; 10]);
+
+    let image = build_image(|fs| {
+        fs.set_block_size(MIN_BLOCK_SIZE);
+        fs.push_file(Cursor::new(data), "file", NodeHeader::default()).unwrap();
+    });
+
+    let fs = read_image(image);
+    let node = fs.files().find(|node| node.fullpath == Path::new("/file")).unwrap();
+    let InnerNode::File(file) = &node.inner else { panic!("not a file") };
+    let sizes = file.block_sizes();
+    assert_eq!(sizes.len(), 2);
+    assert!(!sizes[0].uncompressed);
+    assert!(sizes[0].size < MIN_BLOCK_SIZE);
+    assert_eq!(sizes[1], BlockSize { uncompressed: true, size: MIN_BLOCK_SIZE });
+}
+
+#[test]
+fn test_compressor_options_size_mismatch() {
+    use backhand::compression::{CompressionOptions, Gzip};
+
+    let options = Gzip { compression_level: 9, window_size: 15, strategies: 0 };
+    let mut image = build_image(|fs| {
+        fs.set_kind(Kind::new(&Uncompressed));
+        fs.set_compressor(
+            FilesystemCompressor::new(Compressor::Gzip, Some(CompressionOptions::Gzip(options)))
+                .unwrap(),
+        );
+        fs.push_file(Cursor::new(b"data".to_vec()), "file", NodeHeader::default()).unwrap();
+    });
+
+    // label the 8 bytes of gzip options as zstd, which only has 4
+    set_u16(&mut image, superblock::COMPRESSOR, Compressor::Zstd as u16);
+
+    // only warned about, the image is still readable
+    let squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    assert_eq!(squashfs.compression_options_raw().map(<[u8]>::len), Some(8));
+    assert!(squashfs.zstd_options().is_some());
+    let warning = ReadWarning::CompressionOptionsSize { compressor: Compressor::Zstd, size: 8 };
+    assert_eq!(squashfs.warnings(), [warning]);
+    let fs = squashfs.into_filesystem_reader().unwrap();
+    assert_eq!(fs.read_to_string("/file").unwrap(), "data");
+}
+
+#[test]
+fn test_missing_fragment_table() {
+    let mut image = build_image(|fs| {
+        fs.push_file(Cursor::new(vec![1; 100]), "file", NodeHeader::default()).unwrap();
+    });
+
+    let squashfs = Squashfs::from_reader(Cursor::new(image.clone())).unwrap();
+    assert!(squashfs.superblock.frag_count > 0);
+
+    set_u64(&mut image, superblock::FRAG_TABLE, u64::MAX);
+    let ret = Squashfs::from_reader(Cursor::new(image));
+    assert!(matches!(ret, Err(BackhandError::MissingFragmentTable)));
+}
+
+#[test]
+fn test_stat() {
+    let header = NodeHeader::new(0o640, 1000, 100, 1_700_000_000);
+    let image = build_image(|fs| {
+        fs.push_dir("dev", header).unwrap();
+        fs.push_file(Cursor::new(vec![1; 1234]), "file", header).unwrap();
+        fs.push_symlink("file", "link", header).unwrap();
+        fs.push_char_device(0x0103, "dev/null", header).unwrap();
+    });
+
+    let fs = read_image(image);
+    let (_, inode) = fs.iter_with_inodes().find(|(path, _)| *path == Path::new("/file")).unwrap();
+    let file = fs.stat("/file").unwrap();
+    assert_eq!(
+        file,
+        NodeMetadata {
+            node_type: NodeType::File,
+            mode: 0o100640,
+            uid: 1000,
+            gid: 100,
+            mtime: 1_700_000_000,
+            size: 1234,
+            nlink: 1,
+            ino: inode.header.inode_number,
+            rdev: None,
+        }
+    );
+    // followed
+    assert_eq!(fs.stat("/link").unwrap(), file);
+    let link = fs.lstat("/link").unwrap();
+    assert_eq!(link.node_type, NodeType::Symlink);
+    assert_eq!(link.mode, 0o120640);
+    assert_eq!(link.size, 4);
+
+    let dev = fs.stat("/dev/null").unwrap();
+    assert_eq!(dev.node_type, NodeType::CharacterDevice);
+    assert_eq!(dev.rdev, Some((1, 3)));
+    let dir = fs.stat("/dev").unwrap();
+    assert_eq!(dir.mode, 0o040640);
+    assert!(dir.nlink >= 2);
+
+    assert!(matches!(fs.stat("/missing"), Err(BackhandError::FileNotFound)));
+}
+
+#[test]
+#[cfg(feature = "zstd")]
+fn test_zstd_ultra() {
+    use backhand::compression::{CompressionOptions, Zstd};
+    use backhand::MAX_BLOCK_SIZE;
+
+    // repeating pattern, so that matches are found across the whole block
+    let mut state = 0x2545_f491_u32;
+    let pattern: Vec<u8> = (0..0x8000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect();
+    let data = pattern.repeat(MAX_BLOCK_SIZE as usize * 2 / pattern.len());
+
+    let options = CompressionOptions::Zstd(Zstd { compression_level: 22 });
+    let image = build_image(|fs| {
+        fs.set_block_size(MAX_BLOCK_SIZE);
+        fs.set_compressor(FilesystemCompressor::new(Compressor::Zstd, Some(options)).unwrap());
+        fs.push_file(Cursor::new(data.clone()), "file", NodeHeader::default()).unwrap();
+    });
+    assert!(image.len() < data.len() / 4);
+
+    let fs = read_image(image);
+    assert!(read_file(&fs, "/file") == data);
+}
+
+#[test]
+fn test_into_parts() {
+    let image = build_image(|fs| {
+        fs.set_kind(Kind::new(&Uncompressed));
+        fs.push_file(Cursor::new(b"data".to_vec()), "file", NodeHeader::default()).unwrap();
+    });
+    let mut prefixed = vec![0; 0x100];
+    prefixed.extend_from_slice(&image);
+
+    let squashfs = Squashfs::from_reader_with_offset(Cursor::new(prefixed), 0x100).unwrap();
+    let inode_count = squashfs.inodes.len();
+    let mut parts = squashfs.into_parts();
+    assert_eq!(parts.offset, 0x100);
+    assert_eq!(parts.inodes.len(), inode_count);
+    assert_eq!(parts.fragments.as_ref().map(Vec::len), Some(1));
+
+    // the reader starts at the image
+    let mut magic = [0; 4];
+    parts.reader.seek(SeekFrom::Start(0)).unwrap();
+    parts.reader.read_exact(&mut magic).unwrap();
+    assert_eq!(&magic, b"hsqs");
+
+    // the fragment is stored uncompressed, read it straight from the reader
+    let fragment = parts.fragments.unwrap()[0];
+    assert!(!fragment.is_compressed());
+    let mut data = vec![0; fragment.block_size() as usize];
+    parts.reader.seek(SeekFrom::Start(fragment.start)).unwrap();
+    parts.reader.read_exact(&mut data).unwrap();
+    assert_eq!(&data[..4], b"data");
+}
+
+#[test]
+fn test_read_file_from_layout() {
+    let header = NodeHeader::default();
+    let block_size = MIN_BLOCK_SIZE as usize;
+    let mut expected = vec![];
+    let image = build_image(|fs| {
+        fs.set_block_size(MIN_BLOCK_SIZE);
+        // empty, fragment only, full blocks only, and a partial last block
+        for (i, len) in [0, 100, block_size * 2, block_size * 3 + 100].into_iter().enumerate() {
+            let data: Vec<u8> = (0..len).map(|b| (b * (i + 1)) as u8).collect();
+            let name = format!("file{i}");
+            fs.push_file(Cursor::new(data.clone()), &name, header).unwrap();
+            expected.push((format!("/{name}"), data));
+        }
+    });
+
+    let fs = read_image(image);
+    let layouts: Vec<_> = expected
+        .iter()
+        .map(|(path, _)| {
+            let node = fs.files().find(|node| node.fullpath == Path::new(path)).unwrap();
+            let InnerNode::File(file) = &node.inner else { panic!("not a file") };
+            fs.file(&file.basic).layout()
+        })
+        .collect();
+    assert!(layouts[1].block_sizes.is_empty() && layouts[1].fragment.is_some());
+    assert!(layouts[2].fragment.is_none());
+    for ((path, data), layout) in expected.iter().zip(&layouts) {
+        assert_eq!(&fs.read_file_from_layout(layout).unwrap(), data, "{path}");
+    }
+
+    let mut outside = layouts[3].clone();
+    outside.blocks_start = u64::MAX - 1;
+    assert!(matches!(fs.read_file_from_layout(&outside), Err(BackhandError::InvalidBlockLayout)));
+
+    let mut missing_block = layouts[3].clone();
+    missing_block.block_sizes.pop();
+    assert!(matches!(
+        fs.read_file_from_layout(&missing_block),
+        Err(BackhandError::InvalidBlockLayout)
+    ));
+
+    let mut fragment_offset = layouts[1].clone();
+    fragment_offset.fragment.as_mut().unwrap().1 = MIN_BLOCK_SIZE;
+    assert!(matches!(
+        fs.read_file_from_layout(&fragment_offset),
+        Err(BackhandError::InvalidBlockLayout)
+    ));
+
+    // rounding up the block count doesn't overflow
+    let mut huge = layouts[2].clone();
+    huge.file_size = u64::MAX;
+    assert!(matches!(fs.read_file_from_layout(&huge), Err(BackhandError::InvalidBlockLayout)));
+}
+
+/// Decompressors writing into the spare capacity of the output, instead of growing it
+#[test]
+#[cfg(any(feature = "zstd", feature = "lzo"))]
+fn test_read_file_from_layout_capacity_compressors() {
+    let compressors = [
+        #[cfg(feature = "zstd")]
+        Compressor::Zstd,
+        #[cfg(feature = "lzo")]
+        Compressor::Lzo,
+    ];
+    let data: Vec<u8> = (0..MIN_BLOCK_SIZE as usize * 3 + 100).map(|b| (b % 251) as u8).collect();
+    for compressor in compressors {
+        let fs = read_image(build_image(|fs| {
+            fs.set_compressor(FilesystemCompressor::new(compressor, None).unwrap());
+            fs.set_block_size(MIN_BLOCK_SIZE);
+            fs.push_file(Cursor::new(data.clone()), "file", NodeHeader::default()).unwrap();
+        }));
+        assert_eq!(fs.compressor, compressor);
+        let node = fs.node_at_path(Path::new("/file")).unwrap();
+        let InnerNode::File(file) = &node.inner else { panic!("not a file") };
+        let layout = fs.file(&file.basic).layout();
+        assert_eq!(fs.read_file_from_layout(&layout).unwrap(), data, "{compressor:?}");
+        let report = fs.deep_verify().unwrap();
+        assert!(report.is_ok(), "{compressor:?}: {:?}", report.errors);
+    }
+}
+
+/// Images where the directory table is tiny or not present at all
+#[test]
+fn test_near_empty_dir_table() {
+    let header = NodeHeader::default();
+    let only_root = build_image(|_| {});
+    let empty_dirs = build_image(|fs| {
+        fs.push_dir_all("a/b", header).unwrap();
+        fs.push_dir("c", header).unwrap();
+    });
+    let flat = build_image(|fs| {
+        fs.push_file(Cursor::new(vec![1; 10]), "file", header).unwrap();
+        fs.push_file(Cursor::new(vec![]), "empty", header).unwrap();
+    });
+
+    let cases = [
+        (only_root, vec!["/"]),
+        (empty_dirs, vec!["/", "/a", "/a/b", "/c"]),
+        (flat, vec!["/", "/empty", "/file"]),
+    ];
+    let mut flat_image = vec![];
+    for (image, expected) in cases {
+        let squashfs = Squashfs::from_reader(Cursor::new(image.clone())).unwrap();
+        assert!(squashfs.table_block_counts().dir <= 1, "{expected:?}");
+        let fs = squashfs.into_filesystem_reader().unwrap();
+        let paths: Vec<_> = fs.files().map(|node| node.fullpath.clone()).collect();
+        let expected: Vec<_> = expected.iter().map(Path::new).collect();
+        assert_eq!(paths, expected);
+        flat_image = image;
+    }
+
+    // root listing larger than the whole directory table
+    let mut squashfs = Squashfs::from_reader(Cursor::new(flat_image)).unwrap();
+    match &mut squashfs.root_inode.inner {
+        InodeInner::BasicDirectory(dir) => dir.file_size = u16::MAX,
+        InodeInner::ExtendedDirectory(dir) => dir.file_size = u32::from(u16::MAX),
+        _ => panic!("root is not a dir"),
+    }
+    let ret = squashfs.into_filesystem_reader();
+    assert!(matches!(ret, Err(BackhandError::DirectoryNotInDirTable)));
+}
+
+#[test]
+fn test_deep_verify() {
+    let header = NodeHeader::default();
+    let block_size = MIN_BLOCK_SIZE as usize;
+    let mut image = build_image(|fs| {
+        fs.set_block_size(MIN_BLOCK_SIZE);
+        fs.push_dir("dir", header).unwrap();
+        fs.push_file(Cursor::new(vec![1; block_size * 2]), "dir/a", header).unwrap();
+        fs.push_file(Cursor::new(vec![2; block_size * 2]), "dir/b", header).unwrap();
+        fs.push_file(Cursor::new(vec![3; 100]), "c", header).unwrap();
+        fs.push_symlink("c", "link", header).unwrap();
+    });
+
+    let fs = read_image(image.clone());
+    let report = fs.deep_verify().unwrap();
+    assert!(report.is_ok(), "{:?}", report.errors);
+    assert_eq!(report.nodes, 6);
+    assert_eq!(report.files, 3);
+    // 4 data blocks and 1 fragment
+    assert_eq!(report.blocks, 5);
+    assert_eq!(report.bytes, (block_size * 4 + 100) as u64);
+
+    // corrupt the first block of both large files, all errors are reported
+    for path in ["/dir/a", "/dir/b"] {
+        let node = fs.files().find(|node| node.fullpath == Path::new(path)).unwrap();
+        let InnerNode::File(file) = &node.inner else { panic!("not a file") };
+        let layout = fs.file(&file.basic).layout();
+        assert!(!layout.block_sizes[0].uncompressed);
+        let start = layout.blocks_start as usize;
+        let size = layout.block_sizes[0].size as usize;
+        image[start..][..size].fill(0xff);
+    }
+    let fs = read_image(image);
+    let report = fs.deep_verify().unwrap();
+    let paths: Vec<_> = report.errors.iter().map(|error| error.path.clone().unwrap()).collect();
+    assert_eq!(paths, [Path::new("/dir/a"), Path::new("/dir/b")]);
+    assert_eq!(report.files, 1);
+    assert_eq!(report.nodes, 6);
+}
+
+#[test]
+fn test_inode_header_round_trip() {
+    let dir_header = NodeHeader::new(0o1777, 0, 100, 1_000);
+    let file_header = NodeHeader::new(0o4755, 1000, 100, 2_000);
+    let image = build_image(|fs| {
+        fs.push_dir("tmp", dir_header).unwrap();
+        fs.push_file(Cursor::new(vec![1; 10]), "tmp/file", file_header).unwrap();
+        fs.push_symlink("file", "tmp/link", file_header).unwrap();
+    });
+
+    let fs = read_image(image);
+    for (path, header) in [("/tmp", dir_header), ("/tmp/file", file_header)] {
+        let node = fs.files().find(|node| node.fullpath == Path::new(path)).unwrap();
+        assert_eq!(node.header, header, "{path}");
+
+        // stored as indices into the id table
+        let raw = fs.inode_header(path).unwrap();
+        assert_eq!(raw.permissions, header.permissions);
+        assert_eq!(raw.mtime, header.mtime);
+        assert_eq!(fs.id_table[usize::from(raw.uid)].num, header.uid);
+        assert_eq!(fs.id_table[usize::from(raw.gid)].num, header.gid);
+        assert_eq!(raw.inode_number, fs.lstat(path).unwrap().ino);
+    }
+    // the symlink itself, not its target
+    let link = fs.inode_header("/tmp/link").unwrap();
+    assert_ne!(link.inode_number, fs.inode_header("/tmp/file").unwrap().inode_number);
+    assert!(matches!(fs.inode_header("/missing"), Err(BackhandError::FileNotFound)));
+
+    // and survive being written again
+    let mut writer = FilesystemWriter::from_fs_reader(&fs).unwrap();
+    let mut image = Cursor::new(vec![]);
+    writer.write(&mut image).unwrap();
+    let rewritten = read_image(image.into_inner());
+    let headers = |fs: &FilesystemReader| -> Vec<_> {
+        fs.files().map(|node| (node.fullpath.clone(), node.header)).collect()
+    };
+    assert_eq!(headers(&fs), headers(&rewritten));
+}
+
+#[test]
+fn test_read_dir_entries_with_inode_numbers() {
+    let header = NodeHeader::default();
+    let mut image = build_image(|fs| {
+        fs.set_kind(Kind::new(&Uncompressed));
+        fs.push_dir("dir", header).unwrap();
+        fs.push_file(Cursor::new(vec![1; 10]), "dir/link_a", header).unwrap();
+        fs.push_file(Cursor::new(vec![2; 10]), "dir/link_b", header).unwrap();
+        fs.push_symlink("link_a", "dir/symlink", header).unwrap();
+    });
+
+    let fs = read_image(image.clone());
+    let entries: Vec<_> = fs.read_dir_entries_with_inode_numbers("/dir").unwrap().collect();
+    let names: Vec<_> = entries.iter().map(|entry| entry.name.to_str().unwrap()).collect();
+    assert_eq!(names, ["link_a", "link_b", "symlink"]);
+    let types: Vec<_> = entries.iter().map(|entry| entry.node_type).collect();
+    assert_eq!(types, [NodeType::File, NodeType::File, NodeType::Symlink]);
+    for entry in &entries {
+        assert_eq!(entry.inode_number, fs.lstat(&entry.node.fullpath).unwrap().ino);
+    }
+    assert!(matches!(
+        fs.read_dir_entries_with_inode_numbers("/dir/link_a"),
+        Err(BackhandError::NotADirectory)
+    ));
+
+    // point the entry of `link_b` at the inode of `link_a`, making them hard links
+    let a = find_dir_entry(&image, b"link_a");
+    let b = find_dir_entry(&image, b"link_b");
+    let inode_offset = get_u16(&image, a + dir_entry::INODE_OFFSET);
+    set_u16(&mut image, b + dir_entry::INODE_OFFSET, inode_offset);
+
+    let fs = read_image(image);
+    let entries: Vec<_> = fs.read_dir_entries_with_inode_numbers("/dir").unwrap().collect();
+    assert_eq!(entries[0].inode_number, entries[1].inode_number);
+    assert_ne!(entries[0].inode_number, entries[2].inode_number);
+}
+
+#[test]
+fn test_xattr_table_with_no_xattrs_flag() {
+    let mut image = build_image(|fs| {
+        fs.push_file(Cursor::new(vec![1; 10]), "file", NodeHeader::default()).unwrap();
+    });
+
+    // xattr table past the end of the image
+    let past_end = image.len() as u64 + 0x1000;
+    set_u64(&mut image, superblock::XATTR_TABLE, past_end);
+    let ret = Squashfs::from_reader(Cursor::new(image.clone()));
+    assert!(matches!(ret, Err(BackhandError::CorruptedOrInvalidSquashfs)));
+
+    // the flag wins, the table is ignored
+    let flags = get_u16(&image, superblock::FLAGS) | 0b0000_0010_0000_0000;
+    set_u16(&mut image, superblock::FLAGS, flags);
+    let squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    assert!(squashfs.superblock.no_xattrs_in_archive());
+    assert_eq!(squashfs.superblock.xattr_table_offset(), Some(past_end));
+    assert_eq!(squashfs.superblock.xattr_table_in_use(), None);
+    assert_eq!(squashfs.warnings(), [ReadWarning::XattrTableWithNoXattrsFlag]);
+    let fs = squashfs.into_filesystem_reader().unwrap();
+    assert_eq!(fs.read_to_string("/file").unwrap(), "\u{1}".repeat(10));
+}
+
+#[test]
+fn test_advise() {
+    let image = build_image(|fs| {
+        fs.push_file(Cursor::new(b"data".to_vec()), "file", NodeHeader::default()).unwrap();
+    });
+
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("image.squashfs");
+    std::fs::write(&path, &image).unwrap();
+    let fs = FilesystemReader::from_file(std::fs::File::open(&path).unwrap()).unwrap();
+    fs.advise_sequential().unwrap();
+    assert_eq!(fs.read_to_string("/file").unwrap(), "data");
+    fs.advise_random().unwrap();
+    assert_eq!(fs.read_to_string("/file").unwrap(), "data");
+
+    // not backed by a file, nothing to advise
+    let fs = read_image(image);
+    fs.advise_sequential().unwrap();
+    fs.advise_random().unwrap();
+}
+
+#[test]
+fn test_copy_subtree_to() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir_header = NodeHeader::new(0o750, 0, 0, 0);
+    let header = NodeHeader::new(0o644, 0, 0, 0);
+    let fs = read_image(build_image(|fs| {
+        fs.push_dir_all("lib/modules", dir_header).unwrap();
+        fs.push_file(Cursor::new(vec![1; 10]), "lib/modules/tun.ko", header).unwrap();
+        fs.push_file(Cursor::new(vec![2; 20]), "lib/libc.so", header).unwrap();
+        fs.push_symlink("libc.so", "lib/libc.so.6", header).unwrap();
+        fs.push_symlink("lib", "libs", header).unwrap();
+        fs.push_file(Cursor::new(vec![3; 30]), "library", header).unwrap();
+    }));
+
+    // through a symlink, into a missing destination
+    let tmp = tempfile::tempdir().unwrap();
+    let dest = tmp.path().join("out/lib");
+    let report = fs.copy_subtree_to(Path::new("/libs"), &dest, ExtractOptions::default()).unwrap();
+    assert_eq!(report.dirs, 2);
+    assert_eq!(report.files, 2);
+    assert_eq!(report.symlinks, 1);
+    assert_eq!(report.bytes_written, 30);
+    assert_eq!(std::fs::read(dest.join("modules/tun.ko")).unwrap(), [1; 10]);
+    assert_eq!(std::fs::read_link(dest.join("libc.so.6")).unwrap(), Path::new("libc.so"));
+    assert_eq!(std::fs::metadata(&dest).unwrap().permissions().mode() & 0o777, 0o750);
+    // siblings sharing the prefix are not part of the subtree
+    assert!(!tmp.path().join("out/library").exists());
+    assert!(!dest.join("library").exists());
+
+    let ret = fs.copy_subtree_to(Path::new("/library"), &dest, ExtractOptions::default());
+    assert!(matches!(ret, Err(BackhandError::NotADirectory)));
+    let ret = fs.copy_subtree_to(Path::new("/missing"), &dest, ExtractOptions::default());
+    assert!(matches!(ret, Err(BackhandError::FileNotFound)));
+}
+
+/// Paths of the nodes don't keep unused capacity around
+#[test]
+fn test_node_path_capacity() {
+    let header = NodeHeader::default();
+    let dir = "usr/share/some/long/directory/name";
+    let image = build_image(|fs| {
+        fs.push_dir_all(dir, header).unwrap();
+        for i in 0..100 {
+            fs.push_file(Cursor::new(vec![]), format!("{dir}/file{i}"), header).unwrap();
+        }
+    });
+
+    let fs = read_image(image);
+    assert_eq!(fs.files().count(), 107);
+    for node in fs.files() {
+        assert_eq!(node.path(), node.fullpath.as_path());
+        assert_eq!(node.fullpath.capacity(), node.fullpath.as_os_str().len(), "{node:?}");
+    }
+}
+
+#[test]
+fn test_xattrs() {
+    use backhand::{ExtendedFile, Xattr, XattrPrefix};
+
+    let mut image = build_image(|fs| {
+        fs.push_file(Cursor::new(b"data".to_vec()), "file", NodeHeader::default()).unwrap();
+    });
+    let bytes_used = get_u64(&image, superblock::BYTES_USED);
+    image.truncate(bytes_used as usize);
+
+    // out of line value, followed by the keys
+    let mut kv = vec![];
+    kv.extend_from_slice(&11u32.to_le_bytes());
+    kv.extend_from_slice(b"out of line");
+    let first_key = kv.len() as u64;
+    let label = b"u:object_r:system_file:s0\0";
+    kv.extend_from_slice(&2u16.to_le_bytes());
+    kv.extend_from_slice(&7u16.to_le_bytes());
+    kv.extend_from_slice(b"selinux");
+    kv.extend_from_slice(&(label.len() as u32).to_le_bytes());
+    kv.extend_from_slice(label);
+    kv.extend_from_slice(&0x100u16.to_le_bytes());
+    kv.extend_from_slice(&3u16.to_le_bytes());
+    kv.extend_from_slice(b"big");
+    kv.extend_from_slice(&8u32.to_le_bytes());
+    kv.extend_from_slice(&0u64.to_le_bytes());
+
+    // index 0 has both keys, index 1 only the first
+    let mut ids = vec![];
+    for count in [2u32, 1] {
+        ids.extend_from_slice(&first_key.to_le_bytes());
+        ids.extend_from_slice(&count.to_le_bytes());
+        ids.extend_from_slice(&0u32.to_le_bytes());
+    }
+
+    let kv_start = image.len() as u64;
+    image.extend_from_slice(&set_if_uncompressed(kv.len() as u16).to_le_bytes());
+    image.extend_from_slice(&kv);
+    let ids_start = image.len() as u64;
+    image.extend_from_slice(&set_if_uncompressed(ids.len() as u16).to_le_bytes());
+    image.extend_from_slice(&ids);
+    let xattr_table = image.len() as u64;
+    image.extend_from_slice(&kv_start.to_le_bytes());
+    image.extend_from_slice(&2u32.to_le_bytes());
+    image.extend_from_slice(&0u32.to_le_bytes());
+    image.extend_from_slice(&ids_start.to_le_bytes());
+    let bytes_used = image.len() as u64;
+    set_u64(&mut image, superblock::BYTES_USED, bytes_used);
+    set_u64(&mut image, superblock::XATTR_TABLE, xattr_table);
+
+    let selinux =
+        Xattr { prefix: XattrPrefix::Security, name: b"selinux".to_vec(), value: label.to_vec() };
+    let big =
+        Xattr { prefix: XattrPrefix::User, name: b"big".to_vec(), value: b"out of line".to_vec() };
+    assert_eq!(selinux.full_name(), b"security.selinux");
+
+    let mut squashfs = Squashfs::from_reader(Cursor::new(image.clone())).unwrap();
+    let expected: Vec<Arc<[Xattr]>> =
+        vec![Arc::from(vec![selinux.clone(), big.clone()]), Arc::from(vec![selinux.clone()])];
+    assert_eq!(squashfs.xattrs, Some(expected));
+
+    // only extended inodes have an xattr index
+    let (key, file) = squashfs
+        .inodes
+        .iter()
+        .find_map(|(key, inode)| match &inode.inner {
+            InodeInner::BasicFile(file) => Some((*key, file.clone())),
+            _ => None,
+        })
+        .unwrap();
+    let extended = ExtendedFile {
+        blocks_start: u64::from(file.blocks_start),
+        file_size: u64::from(file.file_size),
+        sparse: 0,
+        link_count: 1,
+        frag_index: file.frag_index,
+        block_offset: file.block_offset,
+        xattr_index: 0,
+        block_sizes: file.block_sizes,
+    };
+    squashfs.inodes.get_mut(&key).unwrap().inner = InodeInner::ExtendedFile(extended);
+    let fs_xattrs = squashfs.xattrs.clone().unwrap();
+    let fs = squashfs.into_filesystem_reader().unwrap();
+    assert_eq!(fs.xattrs(fs.root.root()), None);
+    let node = fs.root.node_by_path("/file").unwrap();
+    assert_eq!(fs.xattrs(node), Some(&[selinux, big][..]));
+    // shared with the xattr table instead of copied
+    assert!(std::ptr::eq(fs.xattrs(node).unwrap(), &*fs_xattrs[0]));
+    assert_eq!(fs.read_to_string("/file").unwrap(), "data");
+
+    // extended attributes aren't silently dropped
+    let mut out = Cursor::new(vec![]);
+    assert!(matches!(
+        fs.write_to(&mut out),
+        Err(BackhandError::XattrsNotWritten(path)) if path == Path::new("/file")
+    ));
+    assert!(out.into_inner().is_empty());
+
+    // the flag wins over the table
+    let flags = get_u16(&image, superblock::FLAGS) | 0b0000_0010_0000_0000;
+    set_u16(&mut image, superblock::FLAGS, flags);
+    let squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    assert_eq!(squashfs.xattrs, None);
+}
+
+#[test]
+fn test_export_lookup() {
+    use backhand::Export;
+
+    let image = build_image(|fs| {
+        fs.push_dir("dir", NodeHeader::default()).unwrap();
+        fs.push_file(Cursor::new(b"data".to_vec()), "dir/file", NodeHeader::default()).unwrap();
+    });
+
+    // images written by backhand have no export table
+    let mut squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    assert!(squashfs.export.is_none());
+    assert!(matches!(squashfs.export_lookup(1), Err(BackhandError::MissingExportTable)));
+
+    let inode_count = squashfs.inodes.len() as u32;
+    let export: Vec<Export> =
+        (1..=inode_count).map(|n| Export { num: squashfs.inode_refs[&n].0 }).collect();
+    squashfs.export = Some(export);
+    // the table is only used with the flag set
+    assert!(matches!(squashfs.export_lookup(1), Err(BackhandError::MissingExportTable)));
+    squashfs.superblock.flags |= 0b0000_0000_1000_0000;
+
+    for n in 1..=inode_count {
+        assert_eq!(squashfs.export_lookup(n).unwrap().header.inode_number, n);
+        let inode = squashfs.inode_by_ref(squashfs.inode_refs[&n]).unwrap();
+        assert_eq!(inode.header.inode_number, n);
+    }
+    assert!(squashfs.inode_by_ref(backhand::InodeRef(u64::MAX)).is_none());
+    assert!(matches!(squashfs.export_lookup(0), Err(BackhandError::InvalidInodeNumber(0))));
+    let past_end = inode_count + 1;
+    assert!(matches!(
+        squashfs.export_lookup(past_end),
+        Err(BackhandError::InvalidInodeNumber(n)) if n == i64::from(past_end)
+    ));
+
+    // the reference wins over the number of the entry
+    let export = squashfs.export.as_mut().unwrap();
+    export.swap(0, 1);
+    assert_eq!(squashfs.export_lookup(1).unwrap().header.inode_number, 2);
+}
+
+#[test]
+fn test_node_at_path() {
+    let fs = read_image(build_image(|fs| {
+        fs.push_dir("etc", NodeHeader::default()).unwrap();
+        fs.push_file(Cursor::new(b"root:x:0:0".to_vec()), "etc/passwd", NodeHeader::default())
+            .unwrap();
+        fs.push_symlink("etc/passwd", "passwd", NodeHeader::default()).unwrap();
+    }));
+
+    for path in ["/etc/passwd", "etc/passwd", "/etc/./passwd", "/etc/../etc/passwd"] {
+        let node = fs.node_at_path(Path::new(path)).unwrap();
+        assert_eq!(node.fullpath, Path::new("/etc/passwd"), "{path}");
+    }
+    assert_eq!(fs.node_at_path(Path::new("/")).unwrap().fullpath, Path::new("/"));
+    assert!(fs.node_at_path(Path::new("/etc/shadow")).is_none());
+    // not followed
+    let link = fs.node_at_path(Path::new("/passwd")).unwrap();
+    assert!(matches!(link.inner, InnerNode::Symlink(_)));
+
+    let (mut buf_read, mut buf_decompress) = fs.alloc_read_buffers();
+    for path in ["/etc/passwd", "/passwd"] {
+        let mut bytes = vec![];
+        fs.read_file_at_path(Path::new(path), &mut buf_read, &mut buf_decompress)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(bytes, b"root:x:0:0", "{path}");
+    }
+    let ret = fs.read_file_at_path(Path::new("/etc"), &mut buf_read, &mut buf_decompress);
+    assert!(matches!(ret, Err(BackhandError::NotAFile)));
+    let ret = fs.read_file_at_path(Path::new("/shadow"), &mut buf_read, &mut buf_decompress);
+    assert!(matches!(ret, Err(BackhandError::FileNotFound)));
+}
+
+#[test]
+fn test_file_reader() {
+    use backhand::DataSize;
+
+    let block_size = MIN_BLOCK_SIZE as usize;
+    let blocks: Vec<u8> = (0..block_size * 2).map(|b| (b % 251) as u8).collect();
+    let tail: Vec<u8> = (0..block_size * 3 + 100).map(|b| (b % 241) as u8).collect();
+    let mut fs = read_image(build_image(|fs| {
+        fs.set_block_size(MIN_BLOCK_SIZE);
+        fs.push_file(Cursor::new(b"fragment".to_vec()), "fragment", NodeHeader::default()).unwrap();
+        fs.push_file(Cursor::new(blocks.clone()), "blocks", NodeHeader::default()).unwrap();
+        fs.push_file(Cursor::new(tail.clone()), "tail", NodeHeader::default()).unwrap();
+    }));
+
+    assert_eq!(read_file(&fs, "/fragment"), b"fragment");
+    assert_eq!(read_file(&fs, "/blocks"), blocks);
+    assert_eq!(read_file(&fs, "/tail"), tail);
+
+    // make the first block sparse, skipping its data
+    let index = fs.root.nodes.iter().position(|n| n.fullpath == Path::new("/blocks")).unwrap();
+    let InnerNode::File(file) = &mut fs.root.nodes[index].inner else { panic!("not a file") };
+    file.basic.blocks_start += file.basic.block_sizes[0].size();
+    file.basic.block_sizes[0] = DataSize::new_compressed(0);
+    let mut expected = vec![0; block_size];
+    expected.extend_from_slice(&blocks[block_size..]);
+    assert_eq!(read_file(&fs, "/blocks"), expected);
+
+    // sparse last block, only zeros up to the file size
+    let index = fs.root.nodes.iter().position(|n| n.fullpath == Path::new("/tail")).unwrap();
+    let InnerNode::File(file) = &mut fs.root.nodes[index].inner else { panic!("not a file") };
+    *file.basic.block_sizes.last_mut().unwrap() = DataSize::new_compressed(0);
+    let mut expected = tail[..block_size * 3].to_vec();
+    expected.resize(tail.len(), 0);
+    assert_eq!(read_file(&fs, "/tail"), expected);
+}
+
+#[test]
+fn test_write_to_round_trip() {
+    let header = NodeHeader::new(0o640, 1000, 100, 1_600_000_000);
+    let big: Vec<u8> = (0..0x30000).map(|b| (b % 251) as u8).collect();
+    let first = read_image(build_image(|fs| {
+        fs.set_time(1_700_000_000);
+        fs.push_dir("etc", header).unwrap();
+        fs.push_file(Cursor::new(b"root:x:0:0".to_vec()), "etc/passwd", header).unwrap();
+        fs.push_file(Cursor::new(big.clone()), "big", NodeHeader::default()).unwrap();
+        fs.push_symlink("etc/passwd", "passwd", header).unwrap();
+    }));
+    let mut once = Cursor::new(vec![]);
+    let (superblock, len) = first.write_to(&mut once).unwrap();
+    assert_eq!(len, once.get_ref().len() as u64);
+    assert_eq!(superblock.mod_time, 1_700_000_000);
+
+    let second = read_image(once.get_ref().clone());
+    let nodes = |fs: &FilesystemReader| -> Vec<_> {
+        fs.files().map(|node| (node.fullpath.clone(), node.header)).collect()
+    };
+    assert_eq!(nodes(&first), nodes(&second));
+    assert_eq!(second.mod_time, first.mod_time);
+    assert_eq!(second.read_to_string("/passwd").unwrap(), "root:x:0:0");
+    assert_eq!(read_file(&second, "/big"), big);
+
+    // unchanged contents give the same bytes
+    let mut twice = Cursor::new(vec![]);
+    second.write_to(&mut twice).unwrap();
+    assert_eq!(once.into_inner(), twice.into_inner());
+}
+
+#[test]
+#[cfg(feature = "lzo")]
+fn test_lzo_round_trip() {
+    let blocks: Vec<u8> = (0..MIN_BLOCK_SIZE as usize * 3 + 100).map(|b| (b % 251) as u8).collect();
+    let image = build_image(|fs| {
+        fs.set_compressor(FilesystemCompressor::new(Compressor::Lzo, None).unwrap());
+        fs.set_block_size(MIN_BLOCK_SIZE);
+        fs.push_dir("etc", NodeHeader::default()).unwrap();
+        fs.push_file(Cursor::new(b"root:x:0:0".to_vec()), "etc/passwd", NodeHeader::default())
+            .unwrap();
+        fs.push_file(Cursor::new(blocks.clone()), "blocks", NodeHeader::default()).unwrap();
+    });
+
+    let fs = read_image(image);
+    assert_eq!(fs.compressor, Compressor::Lzo);
+    assert_eq!(fs.read_to_string("/etc/passwd").unwrap(), "root:x:0:0");
+    assert_eq!(read_file(&fs, "/blocks"), blocks);
+    let node = fs.node_at_path(Path::new("/blocks")).unwrap();
+    let InnerNode::File(file) = &node.inner else { panic!("not a file") };
+    // decompressing into buffers without any capacity yet
+    assert_eq!(fs.read_file_from_layout(&fs.file(&file.basic).layout()).unwrap(), blocks);
+    assert!(fs.deep_verify().unwrap().is_ok());
+}
+
+#[test]
+fn test_corrupt_inode() {
+    let image = build_image(|fs| {
+        fs.set_kind(Kind::new(&Uncompressed));
+        fs.push_file(Cursor::new(b"data".to_vec()), "file", NodeHeader::default()).unwrap();
+        fs.push_dir("dir", NodeHeader::default()).unwrap();
+    });
+    let inode_table = get_u64(&image, superblock::INODE_TABLE) as usize;
+    let dir_table = get_u64(&image, superblock::DIR_TABLE) as usize;
+    // a single uncompressed metadata block
+    let len = dir_table - inode_table - 2;
+    assert_eq!(get_u16(&image, inode_table), set_if_uncompressed(len as u16));
+
+    // unknown type of the first inode
+    let mut bad_type = image.clone();
+    set_u16(&mut bad_type, inode_table + 2, 0xff);
+    let ret = Squashfs::from_reader(Cursor::new(bad_type)).err();
+    assert!(matches!(ret, Some(BackhandError::CorruptInode { offset: 0, .. })), "{ret:?}");
+
+    // the last inode is cut off by an empty block ending the table
+    let mut truncated = image;
+    set_u16(&mut truncated, inode_table, set_if_uncompressed(len as u16 - 2));
+    set_u16(&mut truncated, dir_table - 2, set_if_uncompressed(0));
+    match Squashfs::from_reader(Cursor::new(truncated)).err() {
+        Some(BackhandError::CorruptInode { offset, .. }) => {
+            assert!(offset > 0 && offset < len - 2, "{offset}");
+        }
+        ret => panic!("{ret:?}"),
+    }
+}
+
+#[test]
+fn test_corrupt_directory() {
+    let mut image = build_image(|fs| {
+        fs.set_kind(Kind::new(&Uncompressed));
+        fs.push_file(Cursor::new(b"data".to_vec()), "file", NodeHeader::default()).unwrap();
+    });
+
+    // unknown type of the only entry of the root
+    let entry_type = find_dir_entry(&image, b"file") + dir_entry::TYPE;
+    assert_eq!(get_u16(&image, entry_type), 2);
+    set_u16(&mut image, entry_type, 0xff);
+    let squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    let ret = squashfs.into_filesystem_reader().err();
+    assert!(
+        matches!(ret, Some(BackhandError::CorruptDirectory { block_index: 0, offset: 0, .. })),
+        "{ret:?}"
+    );
+}
+
+#[test]
+fn test_find_offset() {
+    let image = build_image(|fs| {
+        fs.push_file(Cursor::new(b"data".to_vec()), "file", NodeHeader::default()).unwrap();
+    });
+
+    // vendor header, containing a magic not followed by a superblock
+    let mut header = vec![0xaa; 64];
+    header[8..12].copy_from_slice(b"hsqs");
+    let firmware = [&header[..], &image[..]].concat();
+    let mut reader = Cursor::new(firmware);
+    assert_eq!(Squashfs::find_offset(&mut reader), Some(64));
+    let squashfs = Squashfs::from_reader_with_offset(reader, 64).unwrap();
+    let fs = squashfs.into_filesystem_reader().unwrap();
+    assert_eq!(fs.read_to_string("/file").unwrap(), "data");
+
+    // not aligned
+    let firmware = [&header[..62], &[0; 4][..], &image[..]].concat();
+    let mut reader = Cursor::new(firmware);
+    assert_eq!(Squashfs::find_offset(&mut reader), None);
+    assert_eq!(Squashfs::find_offset_with_stride(&mut reader, 1), Some(66));
+    assert_eq!(Squashfs::find_offset_with_stride(&mut reader, 0), Some(66));
+
+    assert_eq!(Squashfs::find_offset(&mut Cursor::new(vec![0; 0x20000])), None);
+}
+
+#[test]
+fn test_read_dir() {
+    let image = build_image(|fs| {
+        fs.push_dir("usr", NodeHeader::default()).unwrap();
+        fs.push_dir("usr/bin", NodeHeader::default()).unwrap();
+        fs.push_file(Cursor::new(b"sh".to_vec()), "usr/bin/sh", NodeHeader::default()).unwrap();
+        fs.push_file(Cursor::new(b"ls".to_vec()), "usr/bin/ls", NodeHeader::default()).unwrap();
+        fs.push_dir("empty", NodeHeader::default()).unwrap();
+    });
+
+    let squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    let names = |inode: &Inode| -> Vec<String> {
+        squashfs.read_dir(inode).map(|entry| entry.unwrap().name.into_string().unwrap()).collect()
+    };
+    assert_eq!(names(&squashfs.root_inode), ["empty", "usr"]);
+
+    let usr = squashfs.read_dir(&squashfs.root_inode).map(Result::unwrap).last().unwrap();
+    assert_eq!(squashfs.inodes[&usr.inode_number], *usr.inode);
+    let bin = squashfs.read_dir(usr.inode).next().unwrap().unwrap();
+    assert_eq!(names(bin.inode), ["ls", "sh"]);
+
+    let empty = squashfs.read_dir(&squashfs.root_inode).next().unwrap().unwrap();
+    assert!(names(empty.inode).is_empty());
+
+    // files have no entries
+    let sh = squashfs.read_dir(bin.inode).last().unwrap().unwrap();
+    let mut entries = squashfs.read_dir(sh.inode);
+    assert!(matches!(entries.next(), Some(Err(BackhandError::UnexpectedInode(_)))));
+    assert!(entries.next().is_none());
+}
+
+#[test]
+fn test_extended_sparse_file() {
+    use backhand::{DataSize, ExtendedFile};
+
+    let block_size = MIN_BLOCK_SIZE as usize;
+    let data: Vec<u8> = (0..block_size * 2).map(|b| (b % 251) as u8).collect();
+    let image = build_image(|fs| {
+        fs.set_block_size(MIN_BLOCK_SIZE);
+        fs.push_file(Cursor::new(data.clone()), "file", NodeHeader::default()).unwrap();
+    });
+    let mut squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+
+    // replace the inode with an extended inode, storing the first block as sparse
+    let inode = squashfs
+        .inodes
+        .values_mut()
+        .find(|inode| matches!(inode.inner, InodeInner::BasicFile(_)))
+        .unwrap();
+    let InodeInner::BasicFile(basic) = &inode.inner else { unreachable!() };
+    let mut block_sizes = basic.block_sizes.clone();
+    block_sizes[0] = DataSize::new_compressed(0);
+    inode.inner = InodeInner::ExtendedFile(ExtendedFile {
+        blocks_start: u64::from(basic.blocks_start + basic.block_sizes[0].size()),
+        file_size: u64::from(basic.file_size),
+        sparse: block_size as u64,
+        link_count: 1,
+        frag_index: basic.frag_index,
+        block_offset: basic.block_offset,
+        xattr_index: u32::MAX,
+        block_sizes,
+    });
+
+    let fs = squashfs.into_filesystem_reader().unwrap();
+    let node = fs.node_at_path(Path::new("/file")).unwrap();
+    let InnerNode::File(file) = &node.inner else { panic!("not a file") };
+    assert_eq!(file.sparse(), block_size as u64);
+    let mut expected = vec![0; block_size];
+    expected.extend_from_slice(&data[block_size..]);
+    assert_eq!(read_file(&fs, "/file"), expected);
+}
+
+#[test]
+fn test_corrupt_superblock_block_size() {
+    let image = build_image(|fs| {
+        fs.push_file(Cursor::new(b"data".to_vec()), "file", NodeHeader::default()).unwrap();
+    });
+
+    let patch = |block_size: u32, block_log: u16| {
+        let mut image = image.clone();
+        set_u32(&mut image, superblock::BLOCK_SIZE, block_size);
+        set_u16(&mut image, superblock::BLOCK_LOG, block_log);
+        Squashfs::from_reader(Cursor::new(image)).err()
+    };
+    assert!(patch(0x2_0000, 17).is_none());
+    for (block_size, block_log) in [(0x2_0000, 16), (0x3000, 12), (0x800, 11), (0x20_0000, 21)] {
+        let ret = patch(block_size, block_log);
+        assert!(
+            matches!(
+                ret,
+                Some(BackhandError::CorruptSuperblock { block_size: s, block_log: l })
+                    if s == block_size && l == block_log
+            ),
+            "{ret:?}"
+        );
+    }
+}
+
+#[test]
+fn test_hard_link_groups() {
+    let mut image = build_image(|fs| {
+        fs.set_kind(Kind::new(&Uncompressed));
+        fs.push_dir("dir", NodeHeader::default()).unwrap();
+        for name in ["dir/link_a", "dir/link_b", "dir/link_c", "dir/other"] {
+            fs.push_file(Cursor::new(name.as_bytes().to_vec()), name, NodeHeader::default())
+                .unwrap();
+        }
+    });
+
+    let fs = read_image(image.clone());
+    assert!(fs.hard_link_groups().is_empty());
+
+    // point the entries of `link_b` and `link_c` at the inode of `link_a`: the offset into the
+    // inode block, and the inode offset
+    let a = find_dir_entry(&image, b"link_a");
+    let inode_ref = image[a..][..dir_entry::TYPE].to_vec();
+    for name in [b"link_b", b"link_c"] {
+        let pos = find_dir_entry(&image, name);
+        image[pos..][..dir_entry::TYPE].copy_from_slice(&inode_ref);
+    }
+
+    let fs = read_image(image);
+    let groups = fs.hard_link_groups();
+    let expected = ["/dir/link_a", "/dir/link_b", "/dir/link_c"].map(Path::new);
+    assert_eq!(groups, [expected]);
+    let a = fs.lstat("/dir/link_a").unwrap();
+    assert_eq!(fs.lstat("/dir/link_c").unwrap().ino, a.ino);
+    assert_ne!(fs.lstat("/dir/other").unwrap().ino, a.ino);
+}
+
+#[test]
+fn test_device_major_minor() {
+    let fs = read_image(build_image(|fs| {
+        fs.push_dir("dev", NodeHeader::default()).unwrap();
+        fs.push_char_device(0x103, "dev/null", NodeHeader::default()).unwrap();
+        fs.push_block_device(0x800, "dev/sda", NodeHeader::default()).unwrap();
+        // the upper bits of the minor are stored above the major
+        fs.push_char_device(0x123a_bc45, "dev/large", NodeHeader::default()).unwrap();
+    }));
+
+    let char_device = |path: &str| {
+        let InnerNode::CharacterDevice(device) = &fs.node_at_path(Path::new(path)).unwrap().inner
+        else {
+            panic!("not a character device")
+        };
+        (device.major(), device.minor())
+    };
+    assert_eq!(char_device("/dev/null"), (1, 3));
+    assert_eq!(char_device("/dev/large"), (0xabc, 0x12345));
+    let InnerNode::BlockDevice(sda) = &fs.node_at_path(Path::new("/dev/sda")).unwrap().inner else {
+        panic!("not a block device")
+    };
+    assert_eq!((sda.major(), sda.minor()), (8, 0));
+    assert_eq!(fs.lstat("/dev/sda").unwrap().rdev, Some((8, 0)));
+}
+
+#[test]
+fn test_modified_time_unsigned() {
+    let mtime = 0x8000_0000;
+    let image = build_image(|fs| {
+        fs.set_time(u32::MAX);
+        // past 2038, negative if read as signed
+        fs.push_file(Cursor::new(vec![1]), "file", NodeHeader::new(0o644, 0, 0, mtime)).unwrap();
+    });
+
+    let squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    let superblock = squashfs.superblock;
+    assert_eq!(superblock.modified_time(), UNIX_EPOCH + Duration::from_secs(u64::from(u32::MAX)));
+    // 2106 is in the future
+    assert_eq!(superblock.modified_time_checked(), None);
+
+    let header = squashfs.inodes.values().find(|inode| inode.header.mtime == mtime).unwrap().header;
+    assert_eq!(header.modified_time(), UNIX_EPOCH + Duration::from_secs(0x8000_0000));
+    let fs = squashfs.into_filesystem_reader().unwrap();
+    let node = fs.node_at_path(Path::new("/file")).unwrap();
+    assert_eq!(node.header.modified_time(), header.modified_time());
+}
+
+#[test]
+#[cfg(feature = "xz")]
+fn test_decompress_legacy_lzma() {
+    use backhand::compression::DefaultCompressor;
+
+    // `.lzma` format: properties, dictionary size, unknown uncompressed size, then the stream
+    #[rustfmt::skip]
+    let block = [
+        0x5d, 0x00, 0x00, 0x01, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x36,
+        0x19, 0x49, 0x29, 0x7a, 0x9a, 0x86, 0x9d, 0x56, 0xbd, 0xc2, 0x6a, 0xfa, 0x8a, 0x33, 0x05,
+        0x1a, 0x63, 0xad, 0x99, 0x4d, 0x33, 0x21, 0x00, 0xbf, 0xff, 0xfe, 0x3f, 0xf0, 0x00,
+    ];
+    let mut out = vec![];
+    DefaultCompressor.decompress(&block, &mut out, Compressor::Lzma).unwrap();
+    assert_eq!(out, b"legacy lzma block ".repeat(4));
+
+    // xz framing is not accepted as legacy lzma
+    let mut out = vec![];
+    let xz = DefaultCompressor
+        .compress(b"xz data", FilesystemCompressor::new(Compressor::Xz, None).unwrap(), 0x1000)
+        .unwrap();
+    assert!(DefaultCompressor.decompress(&xz, &mut out, Compressor::Lzma).is_err());
+}
+
+#[test]
+fn test_reader_builder() {
+    use backhand::SquashfsReaderBuilder;
+
+    // enough small files to fill multiple fragment blocks
+    let files: Vec<(String, Vec<u8>)> =
+        (0..16u8).map(|i| (format!("a/b/{i}"), vec![i; 1000])).collect();
+    let image = build_image(|fs| {
+        fs.set_block_size(MIN_BLOCK_SIZE);
+        fs.push_dir("a", NodeHeader::default()).unwrap();
+        fs.push_dir("a/b", NodeHeader::default()).unwrap();
+        for (path, data) in &files {
+            fs.push_file(Cursor::new(data.clone()), path, NodeHeader::default()).unwrap();
+        }
+    });
+    let firmware = [&[0xaa; 0x100][..], &image[..]].concat();
+
+    let squashfs = SquashfsReaderBuilder::new()
+        .offset(0x100)
+        .kind(Kind::from_target("le_v4_0").unwrap())
+        .build(Cursor::new(firmware.clone()))
+        .unwrap();
+    assert!(squashfs.fragments.as_ref().unwrap().len() > 2);
+
+    for capacity in [0, 1, 100] {
+        let squashfs = SquashfsReaderBuilder::new()
+            .offset(0x100)
+            .cache_capacity(capacity)
+            .build(Cursor::new(firmware.clone()))
+            .unwrap();
+        let fs = squashfs.into_filesystem_reader().unwrap();
+        // read in reverse, so that evicted fragments need to be read again
+        for (path, data) in files.iter().rev().chain(&files) {
+            assert_eq!(
+                &read_file(&fs, &format!("/{path}")),
+                data,
+                "{path} with capacity {capacity}"
+            );
+        }
+    }
+
+    let squashfs =
+        SquashfsReaderBuilder::new().offset(0x100).max_dir_depth(1).build(Cursor::new(firmware));
+    let ret = squashfs.unwrap().into_filesystem_reader().err();
+    assert!(matches!(ret, Some(BackhandError::MaxDepthExceeded)), "{ret:?}");
+}
+
+/// Problems otherwise recovered from are returned as errors in strict mode
+#[test]
+fn test_reader_builder_strict() {
+    use backhand::SquashfsReaderBuilder;
+
+    let header = NodeHeader::new(0o644, 1000, 100, 0);
+    let image = build_image(|fs| {
+        fs.push_file(Cursor::new(vec![1]), "file", header).unwrap();
+    });
+    let open = |image: &[u8], strict| {
+        SquashfsReaderBuilder::new().strict(strict).build(Cursor::new(image.to_vec()))
+    };
+    open(&image, true).unwrap().into_filesystem_reader().unwrap();
+
+    // found while opening
+    let mut no_ids = image.clone();
+    set_u16(&mut no_ids, superblock::ID_COUNT, 0);
+    assert!(open(&no_ids, false).is_ok());
+    let ret = open(&no_ids, true).err();
+    assert!(matches!(ret, Some(BackhandError::Strict(ReadWarning::EmptyIdTable))), "{ret:?}");
+
+    // found while creating the `FilesystemReader`
+    let missing = get_u16(&image, superblock::ID_COUNT) - 1;
+    let mut missing_id = image;
+    set_u16(&mut missing_id, superblock::ID_COUNT, missing);
+    open(&missing_id, false).unwrap().into_filesystem_reader().unwrap();
+    let ret = open(&missing_id, true).unwrap().into_filesystem_reader().err();
+    assert!(
+        matches!(
+            ret,
+            Some(BackhandError::Strict(ReadWarning::IdIndexOutOfRange { index, .. }))
+                if index == missing
+        ),
+        "{ret:?}"
+    );
+}
+
+/// The inode stays with its node when `root` is modified
+#[test]
+fn test_node_inode_after_modifying_root() {
+    let mut fs = read_image(build_image(|fs| {
+        fs.push_dir("dir", NodeHeader::default()).unwrap();
+        fs.push_file(Cursor::new(vec![1; 10]), "dir/a", NodeHeader::default()).unwrap();
+        fs.push_file(Cursor::new(vec![2; 20]), "dir/b", NodeHeader::default()).unwrap();
+    }));
+    let b = fs.lstat("/dir/b").unwrap();
+    assert_eq!(fs.node_at_path(Path::new("/dir/b")).unwrap().inode_number, Some(b.ino));
+
+    // remove `/dir/a`, shifting the index of `/dir/b`
+    let index = fs.root.nodes.iter().position(|node| node.fullpath == Path::new("/dir/a")).unwrap();
+    fs.root.nodes.remove(index);
+    assert_eq!(fs.lstat("/dir/b").unwrap(), b);
+    assert_eq!(fs.inode_header("/dir/b").unwrap().inode_number, b.ino);
+    let entries: Vec<_> = fs.read_dir_entries_with_inode_numbers("/dir").unwrap().collect();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].inode_number, b.ino);
+    let (path, inode) = fs.iter_with_inodes().last().unwrap();
+    assert_eq!((path, inode.header.inode_number), (Path::new("/dir/b"), b.ino));
+    assert!(fs.hard_link_groups().is_empty());
+}
+
+#[test]
+fn test_symlink_target_not_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let target = OsStr::from_bytes(b"target\xff");
+    let image = build_image(|fs| {
+        fs.set_kind(Kind::new(&Uncompressed));
+        fs.push_symlink(target, "link", NodeHeader::default()).unwrap();
+    });
+
+    let squashfs = Squashfs::from_reader(Cursor::new(image)).unwrap();
+    let symlink = squashfs
+        .inodes
+        .values()
+        .find_map(|inode| match &inode.inner {
+            InodeInner::BasicSymlink(symlink) => Some(symlink),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(symlink.target_path, b"target\xff");
+    assert_eq!(symlink.target(), "target\u{fffd}");
+    assert!(format!("{symlink:?}").contains("target\u{fffd}"));
+}
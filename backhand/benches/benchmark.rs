@@ -1,7 +1,7 @@
 use std::fs::File;
 use std::io::{BufReader, Cursor};
 
-use backhand::{FilesystemReader, FilesystemWriter};
+use backhand::{FilesystemReader, FilesystemWriter, InnerNode};
 use criterion::*;
 use test_assets::TestAssetDef;
 
@@ -15,6 +15,41 @@ fn read_write(file: File, offset: u64) {
     black_box(new_filesystem.write(&mut output).unwrap());
 }
 
+/// Read all files from `threads` threads at once
+fn read_files_concurrent(filesystem: &FilesystemReader, threads: usize) {
+    let files: Vec<_> = filesystem
+        .files()
+        .filter_map(|node| match &node.inner {
+            InnerNode::File(file) => Some(&file.basic),
+            _ => None,
+        })
+        .collect();
+    std::thread::scope(|s| {
+        for chunk in files.chunks(files.len() / threads + 1) {
+            s.spawn(move || {
+                let (mut buf_read, mut buf_decompress) = filesystem.alloc_read_buffers();
+                for basic in chunk {
+                    let mut reader =
+                        filesystem.file(basic).reader(&mut buf_read, &mut buf_decompress);
+                    black_box(std::io::copy(&mut reader, &mut std::io::sink()).unwrap());
+                }
+            });
+        }
+    });
+}
+
+/// Read all files, one after the other
+fn read_files_sequential(filesystem: &FilesystemReader) {
+    let (mut buf_read, mut buf_decompress) = filesystem.alloc_read_buffers();
+    for node in filesystem.files() {
+        if let InnerNode::File(file) = &node.inner {
+            let mut reader =
+                filesystem.file(&file.basic).reader(&mut buf_read, &mut buf_decompress);
+            black_box(std::io::copy(&mut reader, &mut std::io::sink()).unwrap());
+        }
+    }
+}
+
 fn read(file: File, offset: u64) {
     let file = BufReader::new(file);
     black_box(FilesystemReader::from_reader_with_offset(file, offset).unwrap());
@@ -106,5 +141,77 @@ pub fn bench_read(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_read_write, bench_read);
+pub fn bench_read_concurrent(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read_concurrent");
+    group.sampling_mode(SamplingMode::Flat);
+    group.sample_size(10);
+
+    const FILE_NAME: &str = "img-1571203182_vol-ubi_rootfs.ubifs";
+    let asset_defs = [TestAssetDef {
+        filename: FILE_NAME.to_string(),
+        hash: "e6adbea10615a8ed9f88e403e2478010696f421f4d69a790d37d97fe8921aa81".to_string(),
+        url: format!("https://wcampbell.dev/squashfs/testing/test_tplink1800/{FILE_NAME}"),
+    }];
+    const TEST_PATH: &str = "test-assets/test_tplink_ax1800";
+    test_assets::download_test_files(&asset_defs, TEST_PATH, true).unwrap();
+    let og_path = format!("{TEST_PATH}/{FILE_NAME}");
+
+    const THREADS: usize = 32;
+    let file = BufReader::new(File::open(&og_path).unwrap());
+    let filesystem = FilesystemReader::from_reader(file).unwrap();
+    group.bench_function("tplink_ax1800_per_read", |b| {
+        b.iter(|| read_files_concurrent(&filesystem, THREADS))
+    });
+
+    let file = BufReader::new(File::open(&og_path).unwrap());
+    let filesystem = FilesystemReader::from_reader(file).unwrap().with_thread_pool(4);
+    group.bench_function("tplink_ax1800_thread_pool", |b| {
+        b.iter(|| read_files_concurrent(&filesystem, THREADS))
+    });
+
+    group.finish();
+}
+
+pub fn bench_read_sequential(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read_sequential");
+    group.sampling_mode(SamplingMode::Flat);
+    group.sample_size(10);
+
+    const FILE_NAME: &str = "img-1571203182_vol-ubi_rootfs.ubifs";
+    let asset_defs = [TestAssetDef {
+        filename: FILE_NAME.to_string(),
+        hash: "e6adbea10615a8ed9f88e403e2478010696f421f4d69a790d37d97fe8921aa81".to_string(),
+        url: format!("https://wcampbell.dev/squashfs/testing/test_tplink1800/{FILE_NAME}"),
+    }];
+    const TEST_PATH: &str = "test-assets/test_tplink_ax1800";
+    test_assets::download_test_files(&asset_defs, TEST_PATH, true).unwrap();
+    let og_path = format!("{TEST_PATH}/{FILE_NAME}");
+
+    let file = BufReader::new(File::open(&og_path).unwrap());
+    let filesystem = FilesystemReader::from_reader(file).unwrap();
+    group.bench_function("tplink_ax1800", |b| b.iter(|| read_files_sequential(&filesystem)));
+
+    let file = BufReader::new(File::open(&og_path).unwrap());
+    let filesystem = FilesystemReader::from_reader(file).unwrap();
+    let filesystem = filesystem.with_read_ahead(4 * filesystem.block_size as usize).unwrap();
+    group.bench_function("tplink_ax1800_read_ahead", |b| {
+        b.iter(|| read_files_sequential(&filesystem))
+    });
+
+    let filesystem = FilesystemReader::from_file(File::open(&og_path).unwrap()).unwrap();
+    filesystem.advise_sequential().unwrap();
+    group.bench_function("tplink_ax1800_advise_sequential", |b| {
+        b.iter(|| read_files_sequential(&filesystem))
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_read_write,
+    bench_read,
+    bench_read_concurrent,
+    bench_read_sequential
+);
 criterion_main!(benches);
@@ -0,0 +1,70 @@
+//! Access pattern hints for the kernel
+
+use std::fs::File;
+use std::io::BufReader;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+
+use crate::error::BackhandError;
+use crate::filesystem::reader::FilesystemReader;
+
+#[derive(Clone, Copy)]
+enum Advice {
+    Sequential,
+    Random,
+}
+
+impl<'b> FilesystemReader<'b> {
+    /// Call [`Self::from_reader`] with `file`, keeping its file descriptor for
+    /// [`Self::advise_sequential`] and [`Self::advise_random`]
+    pub fn from_file(file: File) -> Result<Self, BackhandError> {
+        #[cfg(unix)]
+        let fd = Some(file.as_raw_fd());
+        #[cfg(not(unix))]
+        let fd = None;
+
+        let mut filesystem = Self::from_reader(BufReader::new(file))?;
+        filesystem.advise_fd = fd;
+        Ok(filesystem)
+    }
+
+    /// Hint the kernel that the image will be read sequentially, such as before extracting all
+    /// files
+    ///
+    /// Issues `posix_fadvise` with `POSIX_FADV_SEQUENTIAL` for the whole file, increasing the
+    /// amount of read-ahead. Does nothing if `self` wasn't created by [`Self::from_file`], or on
+    /// platforms without `posix_fadvise`.
+    pub fn advise_sequential(&self) -> Result<(), BackhandError> {
+        self.advise(Advice::Sequential)
+    }
+
+    /// Hint the kernel that the image will be read in random order, such as for reading single
+    /// files
+    ///
+    /// Same as [`Self::advise_sequential`], but with `POSIX_FADV_RANDOM`, disabling read-ahead.
+    pub fn advise_random(&self) -> Result<(), BackhandError> {
+        self.advise(Advice::Random)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn advise(&self, advice: Advice) -> Result<(), BackhandError> {
+        let Some(fd) = self.advise_fd else {
+            return Ok(());
+        };
+        let advice = match advice {
+            Advice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+            Advice::Random => libc::POSIX_FADV_RANDOM,
+        };
+        // SAFETY: `fd` is owned by `self.reader`, which lives as long as `self`
+        let ret = unsafe { libc::posix_fadvise(fd, 0, 0, advice) };
+        if ret != 0 {
+            return Err(std::io::Error::from_raw_os_error(ret).into());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn advise(&self, _advice: Advice) -> Result<(), BackhandError> {
+        Ok(())
+    }
+}
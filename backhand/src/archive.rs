@@ -0,0 +1,121 @@
+//! Convert image to tar archive
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use tar::{Builder, EntryType, Header};
+use tracing::trace;
+
+use crate::error::BackhandError;
+use crate::filesystem::normalize_squashfs_path;
+use crate::filesystem::reader::FilesystemReader;
+use crate::{InnerNode, Node, SquashfsFileReader};
+
+impl<'b> FilesystemReader<'b> {
+    /// Write the subtree at `base` as a tar archive into `out`
+    ///
+    /// Entries are named relative to `base`, which itself is not included. If `base` isn't a
+    /// directory, the archive contains it as a single entry named after it. File data is streamed
+    /// one block at a time, so memory usage doesn't depend on the size of the files.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::fs::File;
+    /// # use std::io::BufReader;
+    /// # use std::path::Path;
+    /// # use backhand::FilesystemReader;
+    /// let file = BufReader::new(File::open("image.squashfs").unwrap());
+    /// let filesystem = FilesystemReader::from_reader(file).unwrap();
+    /// let out = File::create("etc.tar").unwrap();
+    /// filesystem.to_tar(Path::new("/etc"), out).unwrap();
+    /// ```
+    pub fn to_tar<W: Write>(&self, base: &Path, out: W) -> Result<(), BackhandError> {
+        let base = normalize_squashfs_path(base)?;
+        let base_node = self.root.node_by_path(&base).ok_or(BackhandError::FileNotFound)?;
+        let (mut buf_read, mut buf_decompress) = self.alloc_read_buffers();
+        let mut builder = Builder::new(out);
+
+        if let InnerNode::Dir(_) = base_node.inner {
+            let nodes = self.files().filter(|node| node.fullpath.starts_with(&base));
+            for node in nodes.filter(|node| !std::ptr::eq(*node, base_node)) {
+                let path = node
+                    .fullpath
+                    .strip_prefix(&base)
+                    .map_err(|_| BackhandError::InvalidFilePath)?;
+                self.append_tar_entry(
+                    &mut builder,
+                    node,
+                    path,
+                    &mut buf_read,
+                    &mut buf_decompress,
+                )?;
+            }
+        } else {
+            let name = base.file_name().ok_or(BackhandError::InvalidFilePath)?;
+            self.append_tar_entry(
+                &mut builder,
+                base_node,
+                Path::new(name),
+                &mut buf_read,
+                &mut buf_decompress,
+            )?;
+        }
+
+        builder.into_inner()?.flush()?;
+        Ok(())
+    }
+
+    /// Append `node` to `builder` as an entry named `path`
+    fn append_tar_entry<W: Write>(
+        &self,
+        builder: &mut Builder<W>,
+        node: &Node<SquashfsFileReader>,
+        path: &Path,
+        buf_read: &mut Vec<u8>,
+        buf_decompress: &mut Vec<u8>,
+    ) -> Result<(), BackhandError> {
+        trace!("archiving {}", path.display());
+
+        let mut header = tar_header(node);
+        match &node.inner {
+            InnerNode::Dir(_) => {
+                header.set_entry_type(EntryType::Directory);
+                builder.append_data(&mut header, path, io::empty())?;
+            }
+            InnerNode::File(file) => {
+                header.set_entry_type(EntryType::Regular);
+                header.set_size(u64::from(file.basic.file_size));
+                let reader = self.file(&file.basic).reader(buf_read, buf_decompress);
+                builder.append_data(&mut header, path, reader)?;
+            }
+            InnerNode::Symlink(symlink) => {
+                header.set_entry_type(EntryType::Symlink);
+                builder.append_link(&mut header, path, &symlink.link)?;
+            }
+            InnerNode::CharacterDevice(device) => {
+                header.set_entry_type(EntryType::Char);
+                header.set_device_major(device.major())?;
+                header.set_device_minor(device.minor())?;
+                builder.append_data(&mut header, path, io::empty())?;
+            }
+            InnerNode::BlockDevice(device) => {
+                header.set_entry_type(EntryType::Block);
+                header.set_device_major(device.major())?;
+                header.set_device_minor(device.minor())?;
+                builder.append_data(&mut header, path, io::empty())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Header with the permissions, ownership, and modification time of `node`
+fn tar_header(node: &Node<SquashfsFileReader>) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_mode(u32::from(node.header.permissions));
+    header.set_uid(u64::from(node.header.uid));
+    header.set_gid(u64::from(node.header.gid));
+    header.set_mtime(u64::from(node.header.mtime));
+    header.set_size(0);
+    header
+}
@@ -1,6 +1,7 @@
 //! Types of supported compression algorithms
 
 use std::io::{Cursor, Read};
+use std::ops::RangeInclusive;
 
 use deku::prelude::*;
 #[cfg(feature = "gzip")]
@@ -10,10 +11,12 @@ use flate2::Compression;
 #[cfg(feature = "xz")]
 use xz2::read::{XzDecoder, XzEncoder};
 #[cfg(feature = "xz")]
-use xz2::stream::{Check, Filters, LzmaOptions, MtStreamBuilder};
+use xz2::stream::{Check, Filters, LzmaOptions, MtStreamBuilder, Stream};
 
 use crate::error::BackhandError;
 use crate::filesystem::writer::{CompressionExtra, FilesystemCompressor};
+#[cfg(feature = "xz")]
+use crate::MAX_BLOCK_SIZE;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, DekuRead, DekuWrite, Default)]
 #[deku(endian = "endian", ctx = "endian: deku::ctx::Endian")]
@@ -53,6 +56,59 @@ pub enum CompressionOptions {
     Lzma,
 }
 
+impl CompressionOptions {
+    /// Options of [`Compressor::Gzip`], `None` if these are the options of another compressor
+    pub fn as_gzip(&self) -> Option<&Gzip> {
+        match self {
+            Self::Gzip(options) => Some(options),
+            _ => None,
+        }
+    }
+
+    /// Options of [`Compressor::Lzo`], `None` if these are the options of another compressor
+    pub fn as_lzo(&self) -> Option<&Lzo> {
+        match self {
+            Self::Lzo(options) => Some(options),
+            _ => None,
+        }
+    }
+
+    /// Options of [`Compressor::Xz`], `None` if these are the options of another compressor
+    pub fn as_xz(&self) -> Option<&Xz> {
+        match self {
+            Self::Xz(options) => Some(options),
+            _ => None,
+        }
+    }
+
+    /// Options of [`Compressor::Lz4`], `None` if these are the options of another compressor
+    pub fn as_lz4(&self) -> Option<&Lz4> {
+        match self {
+            Self::Lz4(options) => Some(options),
+            _ => None,
+        }
+    }
+
+    /// Options of [`Compressor::Zstd`], `None` if these are the options of another compressor
+    pub fn as_zstd(&self) -> Option<&Zstd> {
+        match self {
+            Self::Zstd(options) => Some(options),
+            _ => None,
+        }
+    }
+}
+
+/// Valid sizes in bytes of the compression options of `compressor`, `None` if it has no options
+pub(crate) fn compression_options_size(compressor: Compressor) -> Option<RangeInclusive<usize>> {
+    match compressor {
+        Compressor::Gzip | Compressor::Lzo | Compressor::Lz4 => Some(8..=8),
+        // OpenWRT adds two optional u16 fields
+        Compressor::Xz => Some(8..=12),
+        Compressor::Zstd => Some(4..=4),
+        Compressor::None | Compressor::Lzma => None,
+    }
+}
+
 #[derive(Debug, DekuRead, DekuWrite, PartialEq, Eq, Clone, Copy)]
 #[deku(endian = "endian", ctx = "endian: deku::ctx::Endian")]
 pub struct Gzip {
@@ -177,6 +233,17 @@ pub trait CompressionAction {
     ) -> Result<Vec<u8>, BackhandError>;
 }
 
+/// Largest zstd window accepted while decompressing, the maximum supported by zstd
+#[cfg(feature = "zstd")]
+const ZSTD_WINDOW_LOG_MAX: u32 = if cfg!(target_pointer_width = "64") { 31 } else { 30 };
+
+/// Memory limit of the legacy lzma decoder
+///
+/// squashfs-tools uses the block size as the dictionary size, so this is the dictionary of the
+/// largest block with plenty of room for the decoder state.
+#[cfg(feature = "xz")]
+const LZMA_MEMLIMIT: u64 = MAX_BLOCK_SIZE as u64 * 32;
+
 /// Default compressor that handles the compression features that are enabled
 #[derive(Copy, Clone)]
 pub struct DefaultCompressor;
@@ -200,6 +267,14 @@ impl CompressionAction for DefaultCompressor {
                 let mut decoder = XzDecoder::new(bytes);
                 decoder.read_to_end(out)?;
             }
+            // legacy lzma, stored in the `.lzma` format without the xz container
+            #[cfg(feature = "xz")]
+            Compressor::Lzma => {
+                let stream =
+                    Stream::new_lzma_decoder(LZMA_MEMLIMIT).map_err(std::io::Error::from)?;
+                let mut decoder = XzDecoder::new_stream(bytes, stream);
+                decoder.read_to_end(out)?;
+            }
             #[cfg(feature = "lzo")]
             Compressor::Lzo => {
                 out.resize(out.capacity(), 0);
@@ -212,7 +287,11 @@ impl CompressionAction for DefaultCompressor {
             }
             #[cfg(feature = "zstd")]
             Compressor::Zstd => {
-                let mut decoder = zstd::bulk::Decompressor::new().unwrap();
+                let mut decoder = zstd::bulk::Decompressor::new()?;
+                // by default, frames needing a window larger than 2^27 are rejected
+                decoder.set_parameter(zstd::zstd_safe::DParameter::WindowLogMax(
+                    ZSTD_WINDOW_LOG_MAX,
+                ))?;
                 decoder.decompress_to_buffer(bytes, out)?;
             }
             _ => return Err(BackhandError::UnsupportedCompression(compressor)),
@@ -327,3 +406,29 @@ impl CompressionAction for DefaultCompressor {
         }
     }
 }
+
+#[cfg(all(test, feature = "xz"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decompress_lzma_block() {
+        let block: Vec<u8> =
+            (0..MAX_BLOCK_SIZE).map(|b| (b % 251) as u8 ^ (b >> 12) as u8).collect();
+        let mut options = LzmaOptions::new_preset(6).unwrap();
+        options.dict_size(MAX_BLOCK_SIZE);
+        let stream = Stream::new_lzma_encoder(&options).unwrap();
+        let mut compressed = vec![];
+        XzEncoder::new_stream(&block[..], stream).read_to_end(&mut compressed).unwrap();
+
+        let mut out = Vec::with_capacity(block.len());
+        DefaultCompressor.decompress(&compressed, &mut out, Compressor::Lzma).unwrap();
+        assert_eq!(out, block);
+
+        // the dictionary size is right after the properties byte
+        compressed[1..5].copy_from_slice(&(64 * MAX_BLOCK_SIZE).to_le_bytes());
+        let mut out = vec![];
+        assert!(DefaultCompressor.decompress(&compressed, &mut out, Compressor::Lzma).is_err());
+        assert!(out.is_empty());
+    }
+}
@@ -52,6 +52,42 @@ impl DataSize {
     pub fn size(&self) -> u32 {
         self.0 & !DATA_STORED_UNCOMPRESSED
     }
+
+    /// Size word as stored in the image, including the uncompressed bit
+    pub fn raw(&self) -> u32 {
+        self.0
+    }
+}
+
+/// Decoded [`DataSize`] of a single data block
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BlockSize {
+    /// Block is stored without compression
+    pub uncompressed: bool,
+    /// Bytes used by the block in the image, `0` for a sparse block
+    pub size: u32,
+}
+
+impl From<DataSize> for BlockSize {
+    fn from(value: DataSize) -> Self {
+        Self { uncompressed: value.uncompressed(), size: value.size() }
+    }
+}
+
+/// Location of all the data of a file in the image
+///
+/// Returned by [`crate::FilesystemReaderFile::layout`] and read back with
+/// [`crate::FilesystemReader::read_file_from_layout`], without needing the inode of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockLayout {
+    /// Position of the first data block, from the start of the image
+    pub blocks_start: u64,
+    /// Size of each data block, stored one after the other from `blocks_start`
+    pub block_sizes: Vec<BlockSize>,
+    /// Fragment block holding the end of the file, and the offset of the end of the file in it
+    pub fragment: Option<(Fragment, u32)>,
+    /// Size of the file in bytes
+    pub file_size: u64,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
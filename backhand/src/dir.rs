@@ -3,13 +3,13 @@
 //! For each directory inode, the directory table stores a linear list of all entries,
 //! with references back to the inodes that describe those entries.
 
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::os::unix::prelude::OsStrExt;
 use std::path::{Component, Path};
 
 use deku::prelude::*;
 
-use crate::inode::InodeId;
+use crate::inode::{Inode, InodeId};
 use crate::BackhandError;
 
 #[derive(Debug, DekuRead, DekuWrite, Clone, PartialEq, Eq)]
@@ -100,6 +100,37 @@ impl DirectoryIndex {
     }
 }
 
+/// Entry of a directory, returned by [`crate::Squashfs::read_dir`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadDirEntry<'a> {
+    pub name: OsString,
+    pub inode_number: u32,
+    /// Inode of the entry, pass to [`crate::Squashfs::read_dir`] again to descend into a
+    /// directory
+    pub inode: &'a Inode,
+}
+
+/// Entry of the index of an extended directory, returned by [`crate::Squashfs::directory_index`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirIndexEntry {
+    /// Name of the first entry following the directory header this entry points at
+    pub name: OsString,
+    /// Offset of the header, as if the uncompressed directory listing was laid out consecutively
+    pub index: u32,
+    /// Start of the metadata block containing the header, relative to the directory table
+    pub start: u32,
+}
+
+impl From<&DirectoryIndex> for DirIndexEntry {
+    fn from(index: &DirectoryIndex) -> Self {
+        Self {
+            name: OsStr::from_bytes(&index.name).to_os_string(),
+            index: index.index,
+            start: index.start,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -6,6 +6,7 @@ use thiserror::Error;
 
 use crate::compressor::Compressor;
 use crate::inode::InodeInner;
+use crate::squashfs::ReadWarning;
 
 /// Errors generated from library
 #[derive(Error, Debug)]
@@ -51,6 +52,72 @@ pub enum BackhandError {
 
     #[error("file duplicated in squashfs image")]
     DuplicatedFileName,
+
+    #[error("too many levels of symbolic links")]
+    SymlinkLoop,
+
+    #[error("path escapes the squashfs image root")]
+    PathEscapesRoot,
+
+    #[error("not a directory")]
+    NotADirectory,
+
+    #[error("table pointer outside of the metadata region")]
+    CorruptedTablePointer,
+
+    #[error("directory nesting exceeds the maximum depth")]
+    MaxDepthExceeded,
+
+    #[error("root inode is not a directory")]
+    RootNotDirectory,
+
+    #[error("squashfs superblock not found")]
+    SuperblockNotFound,
+
+    #[error("table size exceeds the image size")]
+    TableTooLarge,
+
+    #[error("not a file")]
+    NotAFile,
+
+    #[error("directory entry references invalid inode number {0}")]
+    InvalidInodeNumber(i64),
+
+    #[error("not a symlink")]
+    NotASymlink,
+
+    #[error("symlink target doesn't exist")]
+    DanglingSymlink,
+
+    #[error("metadata block of {0} bytes exceeds the maximum size")]
+    OversizedMetadataBlock(usize),
+
+    #[error("directory listing is not stored in the directory table")]
+    DirectoryNotInDirTable,
+
+    #[error("fragments are present, but the fragment table is not set")]
+    MissingFragmentTable,
+
+    #[error("block layout doesn't match the image")]
+    InvalidBlockLayout,
+
+    #[error("image has no nfs export table")]
+    MissingExportTable,
+
+    #[error("corrupted inode at offset {offset:#x} of the uncompressed inode table: {error:?}")]
+    CorruptInode { offset: usize, error: deku::DekuError },
+
+    #[error("corrupted directory listing at offset {offset:#x} of the directory at {block_index:#x}: {error:?}")]
+    CorruptDirectory { block_index: u64, offset: usize, error: deku::DekuError },
+
+    #[error("corrupted superblock, block_size {block_size:#x} with block_log {block_log} is not a power of two from 4KiB to 1MiB")]
+    CorruptSuperblock { block_size: u32, block_log: u16 },
+
+    #[error("extended attributes of {0:?} can't be written")]
+    XattrsNotWritten(std::path::PathBuf),
+
+    #[error("rejected by strict mode: {0:?}")]
+    Strict(ReadWarning),
 }
 
 impl From<BackhandError> for io::Error {
@@ -61,7 +128,9 @@ impl From<BackhandError> for io::Error {
             Deku(e) => e.into(),
             StringUtf8(e) => Self::new(io::ErrorKind::InvalidData, e),
             StrUtf8(e) => Self::new(io::ErrorKind::InvalidData, e),
-            e @ UnsupportedCompression(_) => Self::new(io::ErrorKind::Unsupported, e),
+            e @ (UnsupportedCompression(_) | XattrsNotWritten(_)) => {
+                Self::new(io::ErrorKind::Unsupported, e)
+            }
             e @ FileNotFound => Self::new(io::ErrorKind::NotFound, e),
             e @ (Unreachable
             | UnexpectedInode(_)
@@ -70,7 +139,28 @@ impl From<BackhandError> for io::Error {
             | InvalidCompressionOption
             | InvalidFilePath
             | UndefineFileName
-            | DuplicatedFileName) => Self::new(io::ErrorKind::InvalidData, e),
+            | DuplicatedFileName
+            | SymlinkLoop
+            | PathEscapesRoot
+            | NotADirectory
+            | CorruptedTablePointer
+            | MaxDepthExceeded
+            | RootNotDirectory
+            | SuperblockNotFound
+            | TableTooLarge
+            | NotAFile
+            | InvalidInodeNumber(_)
+            | NotASymlink
+            | DanglingSymlink
+            | OversizedMetadataBlock(_)
+            | DirectoryNotInDirTable
+            | MissingFragmentTable
+            | InvalidBlockLayout
+            | MissingExportTable
+            | CorruptInode { .. }
+            | CorruptDirectory { .. }
+            | CorruptSuperblock { .. }
+            | Strict(_)) => Self::new(io::ErrorKind::InvalidData, e),
         }
     }
 }
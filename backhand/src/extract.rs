@@ -0,0 +1,343 @@
+//! Extract image to disk
+
+use std::fs::{self, File, OpenOptions, Permissions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+
+use tracing::{trace, warn};
+
+use crate::error::BackhandError;
+use crate::filesystem::node::{InnerNode, Node, SquashfsFileReader};
+use crate::filesystem::reader::FilesystemReader;
+
+/// Options used by [`extract`] and [`FilesystemReader::extract_to`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractOptions {
+    /// Replace files and symlinks already existing in the destination
+    pub overwrite: bool,
+    /// Set permissions of the extracted files and directories to those stored in the image
+    pub preserve_permissions: bool,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self { overwrite: false, preserve_permissions: true }
+    }
+}
+
+/// Summary of [`extract`] and [`FilesystemReader::extract_to`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExtractReport {
+    /// Directories created, including the root
+    pub dirs: usize,
+    /// Files extracted
+    pub files: usize,
+    /// Symlinks created
+    pub symlinks: usize,
+    /// Character and block devices, these are not created
+    pub skipped_devices: usize,
+    /// Total bytes written for all files
+    pub bytes_written: u64,
+}
+
+/// Open the image at `image` and extract all of its contents into `dest`
+///
+/// Call [`FilesystemReader::from_reader`], then [`FilesystemReader::extract_to`].
+///
+/// # Example
+/// ```rust,no_run
+/// # use std::path::Path;
+/// # use backhand::ExtractOptions;
+/// let report = backhand::extract(
+///     Path::new("image.squashfs"),
+///     Path::new("squashfs-root"),
+///     ExtractOptions::default(),
+/// )
+/// .unwrap();
+/// println!("extracted {} files", report.files);
+/// ```
+pub fn extract(
+    image: &Path,
+    dest: &Path,
+    opts: ExtractOptions,
+) -> Result<ExtractReport, BackhandError> {
+    let file = BufReader::new(File::open(image)?);
+    let filesystem = FilesystemReader::from_reader(file)?;
+    filesystem.extract_to(dest, opts)
+}
+
+impl<'b> FilesystemReader<'b> {
+    /// Extract all files, directories, and symlinks into `dest`
+    ///
+    /// Character and block devices are skipped, as creating them requires elevated privileges.
+    /// Ownership and modification times are not restored.
+    pub fn extract_to<P: AsRef<Path>>(
+        &self,
+        dest: P,
+        opts: ExtractOptions,
+    ) -> Result<ExtractReport, BackhandError> {
+        let mut extraction = Extraction::new(self, Path::new("/"), dest.as_ref(), opts);
+        for node in self.files() {
+            extraction.extract(node)?;
+        }
+        extraction.finish()
+    }
+
+    /// Extract the directory `src` and everything below it into `dest`
+    ///
+    /// Paths are kept relative to `src`, so `/lib/modules` ends up as `dest/modules` when
+    /// extracting `/lib`. `dest` is created if missing, and gets the permissions of `src`.
+    /// Symlinks leading to `src` are followed. Returns [`BackhandError::NotADirectory`] if `src`
+    /// is not a directory. Otherwise the same as [`Self::extract_to`].
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::fs::File;
+    /// # use std::io::BufReader;
+    /// # use std::path::Path;
+    /// # use backhand::{ExtractOptions, FilesystemReader};
+    /// let file = BufReader::new(File::open("image.squashfs").unwrap());
+    /// let filesystem = FilesystemReader::from_reader(file).unwrap();
+    /// filesystem
+    ///     .copy_subtree_to(Path::new("/lib"), Path::new("lib"), ExtractOptions::default())
+    ///     .unwrap();
+    /// ```
+    pub fn copy_subtree_to(
+        &self,
+        src: &Path,
+        dest: &Path,
+        opts: ExtractOptions,
+    ) -> Result<ExtractReport, BackhandError> {
+        let src = self.canonicalize(src)?;
+        let src_node = self.root.node_by_path(&src).ok_or(BackhandError::FileNotFound)?;
+        if !matches!(src_node.inner, InnerNode::Dir(_)) {
+            return Err(BackhandError::NotADirectory);
+        }
+
+        let mut extraction = Extraction::new(self, &src, dest, opts);
+        for node in self.files().filter(|node| node.fullpath.starts_with(&src)) {
+            extraction.extract(node)?;
+        }
+        extraction.finish()
+    }
+
+    /// Extract the files and symlinks matching the glob `pattern` into `dest`
+    ///
+    /// A pattern without a `/` matches the file name of each node, such as `*.ko`. Otherwise the
+    /// whole path is matched, such as `/lib/modules/**/*.ko`. `*` matches any part of a name,
+    /// `?` a single byte of a name, and `**` any amount of directories. Matching directories are
+    /// created, but their contents are only extracted if also matching. Parent directories of the
+    /// matches are created with default permissions and not counted in the [`ExtractReport`].
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::fs::File;
+    /// # use std::io::BufReader;
+    /// # use std::path::Path;
+    /// # use backhand::{ExtractOptions, FilesystemReader};
+    /// let file = BufReader::new(File::open("image.squashfs").unwrap());
+    /// let filesystem = FilesystemReader::from_reader(file).unwrap();
+    /// let report = filesystem
+    ///     .extract_matching("*.ko", Path::new("modules"), ExtractOptions::default())
+    ///     .unwrap();
+    /// println!("extracted {} modules, {} bytes", report.files, report.bytes_written);
+    /// ```
+    pub fn extract_matching<P: AsRef<Path>>(
+        &self,
+        pattern: &str,
+        dest: P,
+        opts: ExtractOptions,
+    ) -> Result<ExtractReport, BackhandError> {
+        let dest = dest.as_ref();
+        let pattern = Glob::new(pattern);
+        let mut extraction = Extraction::new(self, Path::new("/"), dest, opts);
+        for node in self.files().filter(|node| pattern.matches(&node.fullpath)) {
+            if let Some(parent) = node.fullpath.parent() {
+                let relative =
+                    parent.strip_prefix("/").map_err(|_| BackhandError::InvalidFilePath)?;
+                fs::create_dir_all(dest.join(relative))?;
+            }
+            extraction.extract(node)?;
+        }
+        extraction.finish()
+    }
+}
+
+/// State of [`FilesystemReader::extract_to`], [`FilesystemReader::copy_subtree_to`], and
+/// [`FilesystemReader::extract_matching`]
+struct Extraction<'a, 'b> {
+    system: &'a FilesystemReader<'b>,
+    // directory of the image extracted as `dest`
+    base: &'a Path,
+    dest: &'a Path,
+    opts: ExtractOptions,
+    report: ExtractReport,
+    // permissions of dirs are set last, so that read-only dirs can still be filled
+    dirs: Vec<(PathBuf, u16)>,
+    buf_read: Vec<u8>,
+    buf_decompress: Vec<u8>,
+}
+
+impl<'a, 'b> Extraction<'a, 'b> {
+    fn new(
+        system: &'a FilesystemReader<'b>,
+        base: &'a Path,
+        dest: &'a Path,
+        opts: ExtractOptions,
+    ) -> Self {
+        let (buf_read, buf_decompress) = system.alloc_read_buffers();
+        Self {
+            system,
+            base,
+            dest,
+            opts,
+            report: ExtractReport::default(),
+            dirs: vec![],
+            buf_read,
+            buf_decompress,
+        }
+    }
+
+    /// Extract `node`, its parent directory must already exist
+    fn extract(&mut self, node: &Node<SquashfsFileReader>) -> Result<(), BackhandError> {
+        let relative =
+            node.fullpath.strip_prefix(self.base).map_err(|_| BackhandError::InvalidFilePath)?;
+        let path = self.dest.join(relative);
+        trace!("extracting {}", path.display());
+
+        match &node.inner {
+            InnerNode::Dir(_) => {
+                fs::create_dir_all(&path)?;
+                self.dirs.push((path, node.header.permissions));
+                self.report.dirs += 1;
+            }
+            InnerNode::File(file) => {
+                remove_existing(&path, self.opts.overwrite)?;
+                // never follow a symlink already present in the destination
+                let out = OpenOptions::new().write(true).create_new(true).open(&path)?;
+                let mut out = BufWriter::new(out);
+                let mut reader = self
+                    .system
+                    .file(&file.basic)
+                    .reader(&mut self.buf_read, &mut self.buf_decompress);
+                self.report.bytes_written += io::copy(&mut reader, &mut out)?;
+                out.flush()?;
+
+                if self.opts.preserve_permissions {
+                    let permissions = Permissions::from_mode(u32::from(node.header.permissions));
+                    fs::set_permissions(&path, permissions)?;
+                }
+                self.report.files += 1;
+            }
+            InnerNode::Symlink(link) => {
+                remove_existing(&path, self.opts.overwrite)?;
+                symlink(&link.link, &path)?;
+                self.report.symlinks += 1;
+            }
+            InnerNode::CharacterDevice(_) | InnerNode::BlockDevice(_) => {
+                warn!("skipping device: {}", node.fullpath.display());
+                self.report.skipped_devices += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the permissions of the extracted directories
+    fn finish(self) -> Result<ExtractReport, BackhandError> {
+        if self.opts.preserve_permissions {
+            for (path, permissions) in self.dirs.iter().rev() {
+                fs::set_permissions(path, Permissions::from_mode(u32::from(*permissions)))?;
+            }
+        }
+        Ok(self.report)
+    }
+}
+
+/// Glob pattern used by [`FilesystemReader::extract_matching`]
+///
+/// Only `*`, `?`, and `**` are supported, there is no escaping or character classes.
+struct Glob<'a> {
+    /// Pattern for each path component
+    components: Vec<&'a [u8]>,
+    /// Only match the file name
+    name_only: bool,
+}
+
+impl<'a> Glob<'a> {
+    fn new(pattern: &'a str) -> Self {
+        let name_only = !pattern.contains('/');
+        let components = pattern.split('/').filter(|c| !c.is_empty()).map(str::as_bytes).collect();
+        Self { components, name_only }
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        let components: Vec<&[u8]> = path
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.as_bytes()),
+                _ => None,
+            })
+            .collect();
+        // the root itself is never matched
+        let Some(name) = components.last() else {
+            return false;
+        };
+        if self.name_only {
+            return match_components(&self.components, &[*name]);
+        }
+        match_components(&self.components, &components)
+    }
+}
+
+fn match_components(pattern: &[&[u8]], components: &[&[u8]]) -> bool {
+    wildcard_match(pattern, components, |p| *p == b"**", |p, component| match_name(p, component))
+}
+
+fn match_name(pattern: &[u8], name: &[u8]) -> bool {
+    wildcard_match(pattern, name, |p| *p == b'*', |p, n| *p == b'?' || p == n)
+}
+
+/// Match all of `items` against `pattern`, where each token either matches any amount of items
+/// if `is_any`, or a single item if `matches`
+///
+/// Only the last `is_any` token is backtracked to, so this takes at most
+/// `pattern.len() * items.len()` steps, instead of being exponential in the amount of wildcards.
+fn wildcard_match<P, I>(
+    pattern: &[P],
+    items: &[I],
+    is_any: impl Fn(&P) -> bool,
+    matches: impl Fn(&P, &I) -> bool,
+) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // token after the last wildcard, and the first item not matched by that wildcard
+    let mut backtrack = None;
+    while i < items.len() {
+        if p < pattern.len() && is_any(&pattern[p]) {
+            p += 1;
+            backtrack = Some((p, i));
+        } else if p < pattern.len() && matches(&pattern[p], &items[i]) {
+            p += 1;
+            i += 1;
+        } else if let Some((after_any, matched)) = backtrack {
+            // let the last wildcard match one more item
+            p = after_any;
+            i = matched + 1;
+            backtrack = Some((after_any, i));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(is_any)
+}
+
+/// Remove the non-directory at `path` if `overwrite`, otherwise error if anything exists
+fn remove_existing(path: &Path, overwrite: bool) -> Result<(), BackhandError> {
+    match path.symlink_metadata() {
+        Ok(metadata) if overwrite && !metadata.is_dir() => Ok(fs::remove_file(path)?),
+        Ok(_) => Err(io::Error::from(io::ErrorKind::AlreadyExists).into()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -1,5 +1,6 @@
 //! In-memory representation of SquashFS filesystem tree used for writing to image
 pub mod node;
+mod pool;
 pub mod reader;
 pub mod writer;
 
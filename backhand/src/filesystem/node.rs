@@ -3,20 +3,28 @@ use std::io::Read;
 use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tracing::warn;
 
 use super::normalize_squashfs_path;
-use crate::data::Added;
+use crate::data::{Added, BlockSize};
 use crate::inode::{BasicFile, InodeHeader};
 use crate::{BackhandError, FilesystemReaderFile, Id};
 
 /// File information for Node
+///
+/// For nodes read from an image, see [`crate::FilesystemReader::inode_header`] for the header
+/// exactly as stored, including the id table indices and the inode number.
 #[derive(Debug, PartialEq, Eq, Default, Clone, Copy)]
 pub struct NodeHeader {
+    /// Permission bits of the mode as stored, without the file type bits
     pub permissions: u16,
-    /// actual value
+    /// actual value, resolved from the id table index stored in the inode
     pub uid: u32,
-    /// actual value
+    /// actual value, resolved from the id table index stored in the inode
     pub gid: u32,
+    /// Seconds since the unix epoch, as stored
     pub mtime: u32,
 }
 
@@ -24,25 +32,54 @@ impl NodeHeader {
     pub fn new(permissions: u16, uid: u32, gid: u32, mtime: u32) -> Self {
         Self { permissions, uid, gid, mtime }
     }
+
+    /// Modification time of this node, in seconds since the unix epoch
+    ///
+    /// This is stored per inode, and is unrelated to `mod_time` of the [`crate::SuperBlock`].
+    pub fn mtime(&self) -> u32 {
+        self.mtime
+    }
+
+    /// `mtime` interpreted as a [`SystemTime`]
+    pub fn modified_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(u64::from(self.mtime))
+    }
 }
 
 impl NodeHeader {
     pub fn from_inode(inode_header: InodeHeader, id_table: &[Id]) -> Self {
         Self {
             permissions: inode_header.permissions,
-            uid: id_table[inode_header.uid as usize].num,
-            gid: id_table[inode_header.gid as usize].num,
+            uid: resolve_id(id_table, inode_header.uid),
+            gid: resolve_id(id_table, inode_header.gid),
             mtime: inode_header.mtime,
         }
     }
 }
 
+/// Lookup `index` in `id_table`, using the index itself as id if missing from the table
+fn resolve_id(id_table: &[Id], index: u16) -> u32 {
+    match id_table.get(usize::from(index)) {
+        Some(id) => id.num,
+        None => {
+            warn!("id index {index} not in id table, using as is");
+            u32::from(index)
+        }
+    }
+}
+
 /// Filesystem Node
 #[derive(Clone, Debug)]
 pub struct Node<T> {
     pub fullpath: PathBuf,
     pub header: NodeHeader,
     pub inner: InnerNode<T>,
+    /// Inode number of the node, `None` if not read from an image
+    ///
+    /// Nodes sharing an inode number are hard links, see
+    /// [`FilesystemReader::iter_with_inodes`](crate::FilesystemReader::iter_with_inodes) for the
+    /// inodes. Not written by [`crate::FilesystemWriter`], which creates new inodes.
+    pub inode_number: Option<u32>,
 }
 
 impl<T> PartialEq for Node<T> {
@@ -64,13 +101,55 @@ impl<T> Ord for Node<T> {
 
 impl<T> Node<T> {
     pub(crate) fn new(fullpath: PathBuf, header: NodeHeader, inner: InnerNode<T>) -> Self {
-        Self { fullpath, header, inner }
+        Self { fullpath, header, inner, inode_number: None }
     }
 
     pub fn new_root(header: NodeHeader) -> Self {
         let fullpath = PathBuf::from("/");
         let inner = InnerNode::Dir(SquashfsDir::default());
-        Self { fullpath, header, inner }
+        Self { fullpath, header, inner, inode_number: None }
+    }
+
+    /// Full path of the node inside the image, starting with `/`
+    pub fn path(&self) -> &Path {
+        &self.fullpath
+    }
+
+    /// Type and permissions in the format of `ls -l`, such as `drwxr-xr-x`
+    ///
+    /// The setuid and setgid bits are shown as `s` in the owner and group execute position, or
+    /// `S` if not executable. The sticky bit is shown as `t` in the others execute position, or `T`.
+    pub fn mode_string(&self) -> String {
+        let kind = match self.inner {
+            InnerNode::File(_) => '-',
+            InnerNode::Symlink(_) => 'l',
+            InnerNode::Dir(_) => 'd',
+            InnerNode::CharacterDevice(_) => 'c',
+            InnerNode::BlockDevice(_) => 'b',
+        };
+        let mode = self.header.permissions;
+        let bit = |mask: u16, c: char| if mode & mask != 0 { c } else { '-' };
+        let special = |exec: u16, mask: u16, set: char| match (mode & exec != 0, mode & mask != 0) {
+            (true, true) => set,
+            (false, true) => set.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        };
+
+        [
+            kind,
+            bit(0o400, 'r'),
+            bit(0o200, 'w'),
+            special(0o100, 0o4000, 's'),
+            bit(0o040, 'r'),
+            bit(0o020, 'w'),
+            special(0o010, 0o2000, 's'),
+            bit(0o004, 'r'),
+            bit(0o002, 'w'),
+            special(0o001, 0o1000, 't'),
+        ]
+        .iter()
+        .collect()
     }
 }
 
@@ -89,6 +168,23 @@ pub enum InnerNode<T> {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct SquashfsFileReader {
     pub basic: BasicFile,
+    pub(crate) sparse: u64,
+}
+
+impl SquashfsFileReader {
+    /// Bytes of the file stored as sparse blocks, read back as zeros
+    ///
+    /// Only stored by extended file inodes, `0` for basic file inodes.
+    pub fn sparse(&self) -> u64 {
+        self.sparse
+    }
+
+    /// Size of each data block of the file, as stored in the inode
+    ///
+    /// Doesn't include the fragment, if the end of the file is stored in one.
+    pub fn block_sizes(&self) -> Vec<BlockSize> {
+        self.basic.block_sizes.iter().copied().map(BlockSize::from).collect()
+    }
 }
 
 /// Read file from other SquashfsFile or an user file
@@ -126,6 +222,114 @@ pub struct SquashfsBlockDevice {
     pub device_number: u32,
 }
 
+impl SquashfsCharacterDevice {
+    /// Major number of `device_number`
+    pub fn major(&self) -> u32 {
+        device_major(self.device_number)
+    }
+
+    /// Minor number of `device_number`
+    pub fn minor(&self) -> u32 {
+        device_minor(self.device_number)
+    }
+}
+
+impl SquashfsBlockDevice {
+    /// Major number of `device_number`
+    pub fn major(&self) -> u32 {
+        device_major(self.device_number)
+    }
+
+    /// Minor number of `device_number`
+    pub fn minor(&self) -> u32 {
+        device_minor(self.device_number)
+    }
+}
+
+// device numbers are stored using the linux `new_encode_dev` encoding
+fn device_major(device_number: u32) -> u32 {
+    (device_number >> 8) & 0xfff
+}
+
+fn device_minor(device_number: u32) -> u32 {
+    (device_number & 0xff) | ((device_number >> 12) & 0xfff00)
+}
+
+/// Type of device, see [`DeviceNodeInfo`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DeviceKind {
+    Character,
+    Block,
+}
+
+/// Type of a [`Node`], see [`NodeMetadata`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NodeType {
+    File,
+    Symlink,
+    Dir,
+    CharacterDevice,
+    BlockDevice,
+}
+
+impl NodeType {
+    /// Type bits of `st_mode`, such as `S_IFREG`
+    pub fn mode_bits(&self) -> u32 {
+        match self {
+            Self::File => 0o100000,
+            Self::Symlink => 0o120000,
+            Self::Dir => 0o040000,
+            Self::CharacterDevice => 0o020000,
+            Self::BlockDevice => 0o060000,
+        }
+    }
+}
+
+impl<T> From<&InnerNode<T>> for NodeType {
+    fn from(inner: &InnerNode<T>) -> Self {
+        match inner {
+            InnerNode::File(_) => Self::File,
+            InnerNode::Symlink(_) => Self::Symlink,
+            InnerNode::Dir(_) => Self::Dir,
+            InnerNode::CharacterDevice(_) => Self::CharacterDevice,
+            InnerNode::BlockDevice(_) => Self::BlockDevice,
+        }
+    }
+}
+
+/// Metadata of a single node, same as returned by `stat(2)`
+///
+/// See [`crate::FilesystemReader::stat`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct NodeMetadata {
+    pub node_type: NodeType,
+    /// Type and permissions, such as `0o100644` for a regular file
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Modification time, in seconds since the unix epoch
+    pub mtime: u32,
+    /// Size of the file data, symlink target, or directory listing, `0` for devices
+    pub size: u64,
+    /// Hard link count
+    pub nlink: u32,
+    /// Inode number
+    pub ino: u32,
+    /// Major and minor device number, for devices
+    pub rdev: Option<(u32, u32)>,
+}
+
+/// Character or block device found in the filesystem
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DeviceNodeInfo {
+    pub path: PathBuf,
+    pub kind: DeviceKind,
+    pub major: u32,
+    pub minor: u32,
+    /// permissions of the node
+    pub mode: u16,
+}
+
 #[derive(Debug, Clone)]
 pub struct Nodes<T> {
     pub nodes: Vec<Node<T>>,
@@ -154,6 +358,14 @@ impl<T> Nodes<T> {
             .map(|found| &mut self.nodes[found])
     }
 
+    pub fn node_by_path<S: AsRef<Path>>(&self, path: S) -> Option<&Node<T>> {
+        let find_path = normalize_squashfs_path(path.as_ref()).ok()?;
+        self.nodes
+            .binary_search_by(|node| node.fullpath.cmp(&find_path))
+            .ok()
+            .map(|found| &self.nodes[found])
+    }
+
     pub fn insert(&mut self, node: Node<T>) -> Result<(), BackhandError> {
         let path = &node.fullpath;
         let parent = node.fullpath.parent().ok_or(BackhandError::InvalidFilePath)?;
@@ -198,3 +410,36 @@ impl<T> Nodes<T> {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(permissions: u16, inner: InnerNode<()>) -> Node<()> {
+        Node::new(PathBuf::from("/a"), NodeHeader::new(permissions, 0, 0, 0), inner)
+    }
+
+    #[test]
+    fn mode_string() {
+        let dir = || InnerNode::Dir(SquashfsDir::default());
+        assert_eq!(node(0o755, dir()).mode_string(), "drwxr-xr-x");
+        assert_eq!(node(0o644, InnerNode::File(())).mode_string(), "-rw-r--r--");
+        assert_eq!(node(0o000, InnerNode::File(())).mode_string(), "----------");
+        let link = SquashfsSymlink { link: PathBuf::from("b") };
+        assert_eq!(node(0o777, InnerNode::Symlink(link)).mode_string(), "lrwxrwxrwx");
+        let device = SquashfsCharacterDevice { device_number: 0 };
+        assert_eq!(node(0o620, InnerNode::CharacterDevice(device)).mode_string(), "crw--w----");
+        let device = SquashfsBlockDevice { device_number: 0 };
+        assert_eq!(node(0o660, InnerNode::BlockDevice(device)).mode_string(), "brw-rw----");
+
+        // setuid, setgid, and sticky, with and without execute
+        assert_eq!(node(0o4755, InnerNode::File(())).mode_string(), "-rwsr-xr-x");
+        assert_eq!(node(0o4644, InnerNode::File(())).mode_string(), "-rwSr--r--");
+        assert_eq!(node(0o2755, dir()).mode_string(), "drwxr-sr-x");
+        assert_eq!(node(0o2745, dir()).mode_string(), "drwxr-Sr-x");
+        assert_eq!(node(0o1777, dir()).mode_string(), "drwxrwxrwt");
+        assert_eq!(node(0o1776, dir()).mode_string(), "drwxrwxrwT");
+        assert_eq!(node(0o7000, InnerNode::File(())).mode_string(), "---S--S--T");
+        assert_eq!(node(0o7777, InnerNode::File(())).mode_string(), "-rwsrwsrwt");
+    }
+}
@@ -0,0 +1,102 @@
+//! Bounded thread pool used for decompressing data blocks
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use tracing::trace;
+
+use crate::compressor::Compressor;
+use crate::error::BackhandError;
+use crate::kinds::Kind;
+
+/// Result of a [`Job`]: the input buffer given back, and the output buffer if successful
+type JobResult = (Vec<u8>, Result<Vec<u8>, BackhandError>);
+
+struct Job {
+    kind: Kind,
+    compressor: Compressor,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    result: Sender<JobResult>,
+}
+
+/// Fixed amount of worker threads, shared by all reads of a [`crate::FilesystemReader`]
+///
+/// Callers block until their block is decompressed, so the total decompression parallelism is
+/// bounded by the amount of workers, no matter how many reads are in flight.
+pub(crate) struct DecompressPool {
+    // `Sender` is only `Sync` since rust 1.72
+    sender: Mutex<Option<Sender<Job>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl DecompressPool {
+    /// Spawn `size` worker threads, at least one
+    pub(crate) fn new(size: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..size.max(1)).map(|_| Self::spawn_worker(Arc::clone(&receiver))).collect();
+        Self { sender: Mutex::new(Some(sender)), workers }
+    }
+
+    fn spawn_worker(receiver: Arc<Mutex<Receiver<Job>>>) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            // only hold the lock while waiting for a job, not while decompressing
+            let job = receiver.lock().unwrap().recv();
+            let Ok(mut job) = job else {
+                // pool was dropped
+                break;
+            };
+            let ret = job
+                .kind
+                .inner
+                .compressor
+                .decompress(&job.input, &mut job.output, job.compressor)
+                .map(|()| job.output);
+            // the reader could have given up waiting, ignore
+            let _ = job.result.send((job.input, ret));
+        })
+    }
+
+    /// Decompress `input_buf` into `output_buf` using one of the workers, blocking until done
+    pub(crate) fn decompress(
+        &self,
+        kind: &Kind,
+        compressor: Compressor,
+        input_buf: &mut Vec<u8>,
+        output_buf: &mut Vec<u8>,
+    ) -> Result<(), BackhandError> {
+        let (result, receiver) = mpsc::channel();
+        // move the buffers into the job, and back again when done
+        let job = Job {
+            kind: Kind::from_kind(kind),
+            compressor,
+            input: std::mem::take(input_buf),
+            output: std::mem::take(output_buf),
+            result,
+        };
+        {
+            let sender = self.sender.lock().unwrap();
+            let sender = sender.as_ref().ok_or(BackhandError::Unreachable)?;
+            sender.send(job).map_err(|_| BackhandError::Unreachable)?;
+        }
+
+        // only fails if the worker panicked
+        let (input, ret) = receiver.recv().map_err(|_| BackhandError::Unreachable)?;
+        *input_buf = input;
+        *output_buf = ret?;
+        Ok(())
+    }
+}
+
+impl Drop for DecompressPool {
+    fn drop(&mut self) {
+        // closing the channel stops all workers
+        self.sender.lock().unwrap().take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        trace!("decompression pool stopped");
+    }
+}
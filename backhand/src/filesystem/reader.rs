@@ -1,17 +1,32 @@
-use std::io::{Read, SeekFrom};
-use std::sync::Mutex;
+use std::cmp::Reverse;
+use std::ffi::{OsStr, OsString};
+use std::hash::Hasher;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::num::NonZeroUsize;
+use std::ops::{Deref, DerefMut};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
-use super::node::Nodes;
+use rustc_hash::FxHashMap;
+
+use super::node::{DeviceKind, DeviceNodeInfo, NodeMetadata, NodeType, Nodes};
+use super::normalize_squashfs_path;
+use super::pool::DecompressPool;
 use crate::compressor::{CompressionOptions, Compressor};
-use crate::data::DataSize;
+use crate::data::{BlockLayout, BlockSize, DataSize};
 use crate::error::BackhandError;
 use crate::fragment::Fragment;
 use crate::id::Id;
-use crate::inode::BasicFile;
+use crate::inode::{BasicFile, Inode, InodeHeader};
 use crate::kinds::Kind;
-use crate::reader::BufReadSeek;
-use crate::squashfs::Cache;
-use crate::{Node, Squashfs, SquashfsFileReader};
+use crate::reader::{BufReadSeek, ReadAhead};
+use crate::squashfs::{checked_mod_time, Cache, SuperBlock};
+use crate::{FilesystemWriter, InnerNode, Node, Squashfs, SquashfsFileReader, Xattr};
+
+/// Maximum amount of symlinks followed while resolving a single path
+const MAX_SYMLINK_FOLLOW: usize = 40;
 
 /// Representation of SquashFS filesystem after read from image
 /// - Use [`Self::from_reader`] to read into `Self` from a `reader`
@@ -85,10 +100,20 @@ pub struct FilesystemReader<'b> {
     pub fragments: Option<Vec<Fragment>>,
     /// All files and directories in filesystem
     pub root: Nodes<SquashfsFileReader>,
+    // Inodes of the nodes read from the image, by inode number
+    pub(crate) inodes: FxHashMap<u32, Inode>,
+    // Extended attributes of each xattr index, `None` if the image has no xattr table
+    pub(crate) xattr_table: Option<Vec<Arc<[Xattr]>>>,
     // File reader
     pub(crate) reader: Mutex<Box<dyn BufReadSeek + 'b>>,
     // Cache used in the decompression
     pub(crate) cache: Mutex<Cache>,
+    // Optional worker threads used for decompression of data
+    pub(crate) decompress_pool: Option<DecompressPool>,
+    // Size of the image, from the superblock
+    pub(crate) bytes_used: u64,
+    // File descriptor of the image, if created by `Self::from_file`
+    pub(crate) advise_fd: Option<std::os::raw::c_int>,
 }
 
 impl<'b> FilesystemReader<'b> {
@@ -108,6 +133,15 @@ impl<'b> FilesystemReader<'b> {
         (buf_read, buf_decompress)
     }
 
+    /// Capacity to reserve for the data of `basic_file`
+    ///
+    /// The size from the inode, but no more than its blocks and fragment can hold.
+    fn data_capacity(&self, basic_file: &BasicFile) -> usize {
+        let block_size = self.block_size as usize;
+        let max = block_size.saturating_mul(basic_file.block_sizes.len() + 1);
+        (basic_file.file_size as usize).min(max)
+    }
+
     /// Same as [`Self::from_reader`], but seek'ing to `offset` in `reader` before reading
     pub fn from_reader_with_offset<R: BufReadSeek + 'b>(
         reader: R,
@@ -127,11 +161,777 @@ impl<'b> FilesystemReader<'b> {
         squashfs.into_filesystem_reader()
     }
 
+    /// `mod_time` interpreted as a [`SystemTime`]
+    ///
+    /// Returns `None` if the value is suspicious, see [`crate::SuperBlock::modified_time_checked`].
+    pub fn modified_time_checked(&self) -> Option<SystemTime> {
+        checked_mod_time(self.mod_time)
+    }
+
+    /// Decompress data using a pool of `size` worker threads, shared by all reads
+    ///
+    /// Reading threads block until their block is decompressed by one of the workers, bounding the
+    /// total decompression parallelism regardless of how many reads are in flight.
+    pub fn with_thread_pool(mut self, size: usize) -> Self {
+        self.decompress_pool = Some(DecompressPool::new(size));
+        self
+    }
+
+    /// Keep the `size` bytes following each read of the image in memory
+    ///
+    /// Reading a file seeks to each of its blocks, defeating the read-ahead of the OS. When
+    /// extracting files in order, their data is stored consecutively, so the next blocks are
+    /// usually already read. Mostly useful for images on slow storage or network filesystems,
+    /// where seeking is expensive. `size` should be a few times the `block_size`.
+    ///
+    /// Returns an error of [`io::ErrorKind::InvalidInput`] if `size` is `0`, and of
+    /// [`io::ErrorKind::Other`] if a previous read panicked while holding the reader.
+    pub fn with_read_ahead(mut self, size: usize) -> Result<Self, BackhandError> {
+        if size == 0 {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput).into());
+        }
+        let reader = self.reader.into_inner().map_err(|_| io::Error::from(io::ErrorKind::Other))?;
+        self.reader = Mutex::new(Box::new(ReadAhead::new(reader, size)?));
+        Ok(self)
+    }
+
+    /// Return the canonical, absolute path inside the image with all symlinks resolved
+    ///
+    /// Like [`std::fs::canonicalize`], every component of `path` must exist. Relative paths are
+    /// resolved from the image root. The final target may be of any type, including a directory.
+    ///
+    /// Returns [`BackhandError::SymlinkLoop`] if too many symlinks are followed, and
+    /// [`BackhandError::PathEscapesRoot`] if `..` would leave the image root.
+    pub fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, BackhandError> {
+        let mut resolved = PathBuf::from(Component::RootDir.as_os_str());
+        let mut pending = vec![];
+        push_components(&mut pending, path.as_ref())?;
+
+        let mut follows = 0;
+        while let Some(component) = pending.pop() {
+            if component == "." {
+                continue;
+            }
+            if component == ".." {
+                if !resolved.pop() {
+                    return Err(BackhandError::PathEscapesRoot);
+                }
+                continue;
+            }
+
+            let candidate = resolved.join(&component);
+            let node = self.root.node_by_path(&candidate).ok_or(BackhandError::FileNotFound)?;
+            match &node.inner {
+                InnerNode::Symlink(symlink) => {
+                    follows += 1;
+                    if follows > MAX_SYMLINK_FOLLOW {
+                        return Err(BackhandError::SymlinkLoop);
+                    }
+                    if symlink.link.has_root() {
+                        resolved = PathBuf::from(Component::RootDir.as_os_str());
+                    }
+                    push_components(&mut pending, &symlink.link)?;
+                }
+                InnerNode::Dir(_) => resolved = candidate,
+                _ => {
+                    // only the last component can be something other than a directory
+                    if !pending.is_empty() {
+                        return Err(BackhandError::InvalidFilePath);
+                    }
+                    resolved = candidate;
+                }
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    /// Reader of the decompressed bytes of `file`, allocating its own buffers
+    ///
+    /// Blocks are read and decompressed only as needed, including the fragment holding the end
+    /// of the file, so memory usage doesn't depend on the size of the file. Sparse blocks are
+    /// read as zeros. Use [`FilesystemReaderFile::reader`] to reuse buffers for many files.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::fs::File;
+    /// # use std::io::{self, BufReader};
+    /// # use backhand::{FilesystemReader, InnerNode};
+    /// let file = BufReader::new(File::open("image.squashfs").unwrap());
+    /// let filesystem = FilesystemReader::from_reader(file).unwrap();
+    /// for node in filesystem.files() {
+    ///     if let InnerNode::File(file) = &node.inner {
+    ///         io::copy(&mut filesystem.file_reader(file), &mut io::sink()).unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub fn file_reader<'a>(&'a self, file: &'a SquashfsFileReader) -> SquashfsReadFile<'a, 'b> {
+        self.file(&file.basic).owned_reader()
+    }
+
+    /// Node at `path`, found with a single lookup of the sorted nodes
+    ///
+    /// The leading `/` is optional, and `.` and `..` components are removed lexically, so
+    /// `etc/../etc/./passwd` finds `/etc/passwd`. Symlinks are not followed, see
+    /// [`Self::canonicalize`] for resolving them first.
+    pub fn node_at_path(&self, path: &Path) -> Option<&Node<SquashfsFileReader>> {
+        self.root.node_by_path(path)
+    }
+
+    /// Reader of the decompressed bytes of the file at `path`, following symlinks
+    ///
+    /// Returns [`BackhandError::FileNotFound`] if missing, and [`BackhandError::NotAFile`] if not
+    /// a regular file. See [`FilesystemReaderFile::reader`] for `buf_read` and `buf_decompress`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::fs::File;
+    /// # use std::io::{self, BufReader};
+    /// # use std::path::Path;
+    /// # use backhand::FilesystemReader;
+    /// let file = BufReader::new(File::open("image.squashfs").unwrap());
+    /// let filesystem = FilesystemReader::from_reader(file).unwrap();
+    /// let (mut buf_read, mut buf_decompress) = filesystem.alloc_read_buffers();
+    /// let mut reader = filesystem
+    ///     .read_file_at_path(Path::new("/etc/passwd"), &mut buf_read, &mut buf_decompress)
+    ///     .unwrap();
+    /// io::copy(&mut reader, &mut io::stdout()).unwrap();
+    /// ```
+    pub fn read_file_at_path<'a>(
+        &'a self,
+        path: &Path,
+        buf_read: &'a mut Vec<u8>,
+        buf_decompress: &'a mut Vec<u8>,
+    ) -> Result<SquashfsReadFile<'a, 'b>, BackhandError> {
+        let path = self.canonicalize(path)?;
+        let node = self.root.node_by_path(path).ok_or(BackhandError::FileNotFound)?;
+        let InnerNode::File(file) = &node.inner else {
+            return Err(BackhandError::NotAFile);
+        };
+        Ok(self.file(&file.basic).reader(buf_read, buf_decompress))
+    }
+
+    /// Root directory node of the image, the starting point for traversing the tree
+    ///
+    /// Named `root_node`, as [`Self::root`] already holds all of the nodes.
+    pub fn root_node(&self) -> &Node<SquashfsFileReader> {
+        self.root.root()
+    }
+
+    /// Returns `true` if `prefix` is a directory in the image
+    ///
+    /// A single lookup of the sorted nodes, cheaper than [`Self::read_dir`] when only checking for
+    /// the presence of optional directories. Symlinks are not followed.
+    pub fn has_subtree(&self, prefix: &Path) -> bool {
+        matches!(self.root.node_by_path(prefix), Some(node) if matches!(node.inner, InnerNode::Dir(_)))
+    }
+
+    /// Iterator of the top-level entries of the image, same as `self.read_dir("/")`
+    pub fn root_entries(&self) -> impl Iterator<Item = DirEntry<'_>> {
+        let root = self.root.root();
+        self.root
+            .children_of(NonZeroUsize::new(1).unwrap())
+            .filter(move |(_, node)| node.fullpath.parent() == Some(root.fullpath.as_path()))
+            .filter_map(|(_, node)| Some(DirEntry { name: node.fullpath.file_name()?, node }))
+    }
+
+    /// Node the symlink at `path` points at, resolving only the link itself
+    ///
+    /// The target is resolved relative to the directory of the link. Symlinks in the directories
+    /// leading to the target are followed, but the target itself is returned even if it is
+    /// another symlink.
+    ///
+    /// Returns [`BackhandError::NotASymlink`] if `path` is not a symlink, and
+    /// [`BackhandError::DanglingSymlink`] if the target doesn't exist.
+    pub fn symlink_target_node<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<&Node<SquashfsFileReader>, BackhandError> {
+        let link_path = self.no_follow_path(path.as_ref())?;
+        let node = self.root.node_by_path(&link_path).ok_or(BackhandError::FileNotFound)?;
+        let InnerNode::Symlink(symlink) = &node.inner else {
+            return Err(BackhandError::NotASymlink);
+        };
+
+        // relative targets start at the directory containing the link
+        let target = link_path.parent().unwrap_or(Path::new("/")).join(&symlink.link);
+        let target = match self.no_follow_path(&target) {
+            Ok(target) => target,
+            Err(BackhandError::FileNotFound | BackhandError::InvalidFilePath) => {
+                return Err(BackhandError::DanglingSymlink)
+            }
+            Err(e) => return Err(e),
+        };
+        self.root.node_by_path(target).ok_or(BackhandError::DanglingSymlink)
+    }
+
+    /// `path` with the symlinks leading to it resolved, but not the last component
+    fn no_follow_path(&self, path: &Path) -> Result<PathBuf, BackhandError> {
+        match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => Ok(self.canonicalize(parent)?.join(name)),
+            // root, or ending in `..`
+            _ => self.canonicalize(path),
+        }
+    }
+
+    /// Total uncompressed size of the files at or below `path`
+    ///
+    /// Symlinks at `path` are not followed, and only regular files are counted. Returns the size of
+    /// the file itself if `path` is a file. Hard links are counted once for each path.
+    pub fn du<P: AsRef<Path>>(&self, path: P) -> Result<u64, BackhandError> {
+        self.du_with(path.as_ref(), |file| u64::from(file.basic.file_size))
+    }
+
+    /// Same as [`Self::du`], but summing the bytes used in the image by the data blocks
+    ///
+    /// Fragment blocks are shared between files, so the part of a file stored in a fragment is
+    /// counted with its uncompressed size.
+    pub fn du_on_disk<P: AsRef<Path>>(&self, path: P) -> Result<u64, BackhandError> {
+        self.du_with(path.as_ref(), |file| {
+            let blocks: u64 = file.basic.block_sizes.iter().map(|b| u64::from(b.size())).sum();
+            let tail = if file.fragment().is_some() {
+                let in_blocks = file.basic.block_sizes.len() as u64 * u64::from(self.block_size);
+                u64::from(file.basic.file_size).saturating_sub(in_blocks)
+            } else {
+                0
+            };
+            blocks + tail
+        })
+    }
+
+    fn du_with(
+        &self,
+        path: &Path,
+        size: impl Fn(FilesystemReaderFile<'_, 'b>) -> u64,
+    ) -> Result<u64, BackhandError> {
+        let base = self.no_follow_path(path)?;
+        self.root.node_by_path(&base).ok_or(BackhandError::FileNotFound)?;
+        Ok(self
+            .files()
+            .filter(|node| node.fullpath.starts_with(&base))
+            .filter_map(|node| match &node.inner {
+                InnerNode::File(file) => Some(size(self.file(&file.basic))),
+                _ => None,
+            })
+            .sum())
+    }
+
+    /// Metadata of the node at `path`, following symlinks
+    ///
+    /// Only the inode is used, file data is never read. Returns [`BackhandError::FileNotFound`]
+    /// if `path` doesn't exist.
+    pub fn stat<P: AsRef<Path>>(&self, path: P) -> Result<NodeMetadata, BackhandError> {
+        self.node_metadata(&self.canonicalize(path)?)
+    }
+
+    /// Same as [`Self::stat`], but returning the metadata of the symlink at `path` itself
+    pub fn lstat<P: AsRef<Path>>(&self, path: P) -> Result<NodeMetadata, BackhandError> {
+        self.node_metadata(&self.no_follow_path(path.as_ref())?)
+    }
+
+    /// Inode header of the node at `path` exactly as stored, without following symlinks
+    ///
+    /// Unlike [`NodeHeader`](crate::NodeHeader), `uid` and `gid` are the indices into
+    /// [`Self::id_table`], and the inode number is included. Returns
+    /// [`BackhandError::FileNotFound`] if `path` doesn't exist or has no inode, such as a node
+    /// added to [`Self::root`] after reading the image.
+    pub fn inode_header<P: AsRef<Path>>(&self, path: P) -> Result<InodeHeader, BackhandError> {
+        let path = self.no_follow_path(path.as_ref())?;
+        let (_, node) = self.node_with_index(&path)?;
+        self.inode(node).map(|inode| inode.header).ok_or(BackhandError::FileNotFound)
+    }
+
+    /// Inode of `node`, `None` if not read from the image
+    pub(crate) fn inode(&self, node: &Node<SquashfsFileReader>) -> Option<&Inode> {
+        self.inodes.get(&node.inode_number?)
+    }
+
+    /// Extended attributes of `node`, `None` if it has none
+    ///
+    /// Looked up through the xattr index of its inode, nodes of the same xattr index share the
+    /// attributes. Only read from images, these are not written by [`FilesystemWriter`], and
+    /// nodes added to [`Self::root`] after reading the image have none.
+    pub fn xattrs(&self, node: &Node<SquashfsFileReader>) -> Option<&[Xattr]> {
+        let index = self.inode(node)?.metadata().xattr_index?;
+        let xattrs = self.xattr_table.as_ref()?.get(index as usize)?;
+        Some(xattrs)
+    }
+
+    fn node_with_index(
+        &self,
+        path: &Path,
+    ) -> Result<(usize, &Node<SquashfsFileReader>), BackhandError> {
+        let index = self
+            .root
+            .nodes
+            .binary_search_by(|node| node.fullpath.as_path().cmp(path))
+            .map_err(|_| BackhandError::FileNotFound)?;
+        Ok((index, &self.root.nodes[index]))
+    }
+
+    fn node_metadata(&self, path: &Path) -> Result<NodeMetadata, BackhandError> {
+        let (_, node) = self.node_with_index(path)?;
+        let metadata = self.inode(node).map(Inode::metadata);
+
+        let node_type = NodeType::from(&node.inner);
+        let (size, rdev) = match &node.inner {
+            InnerNode::File(file) => (u64::from(file.basic.file_size), None),
+            InnerNode::Symlink(symlink) => (symlink.link.as_os_str().len() as u64, None),
+            InnerNode::Dir(_) => (metadata.as_ref().and_then(|m| m.file_size).unwrap_or(0), None),
+            InnerNode::CharacterDevice(dev) => (0, Some((dev.major(), dev.minor()))),
+            InnerNode::BlockDevice(dev) => (0, Some((dev.major(), dev.minor()))),
+        };
+        Ok(NodeMetadata {
+            node_type,
+            mode: node_type.mode_bits() | u32::from(node.header.permissions),
+            uid: node.header.uid,
+            gid: node.header.gid,
+            mtime: node.header.mtime,
+            size,
+            nlink: metadata.as_ref().map_or(1, |m| m.link_count),
+            ino: metadata.as_ref().map_or(0, |m| m.header.inode_number),
+            rdev,
+        })
+    }
+
+    /// Iterator of the direct children of the directory at `path`
+    ///
+    /// SquashFS doesn't store `.` and `..` entries, see [`Self::read_dir_with_dot_entries`] for
+    /// including them.
+    pub fn read_dir<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<impl Iterator<Item = DirEntry<'_>>, BackhandError> {
+        self.inner_read_dir(path.as_ref(), false)
+    }
+
+    /// Same as [`Self::read_dir`], but collecting the entries in `order`
+    ///
+    /// Sorting is stable, entries comparing equal are kept in name order.
+    pub fn read_dir_sorted<P: AsRef<Path>>(
+        &self,
+        path: P,
+        order: DirOrder,
+    ) -> Result<Vec<DirEntry<'_>>, BackhandError> {
+        let mut entries: Vec<_> = self.read_dir(path)?.collect();
+        let file_size = |entry: &DirEntry| match &entry.node.inner {
+            InnerNode::File(file) => file.basic.file_size,
+            _ => 0,
+        };
+        match order {
+            DirOrder::NameAsc => {}
+            DirOrder::NameDesc => entries.reverse(),
+            DirOrder::SizeAsc => entries.sort_by_key(file_size),
+            DirOrder::SizeDesc => entries.sort_by_key(|entry| Reverse(file_size(entry))),
+            DirOrder::MtimeAsc => entries.sort_by_key(|entry| entry.node.header.mtime),
+            DirOrder::DirsFirst => {
+                entries.sort_by_key(|entry| !matches!(entry.node.inner, InnerNode::Dir(_)))
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Same as [`Self::read_dir`], but first yield the synthesized `.` and `..` entries
+    ///
+    /// `.` points at the directory itself and `..` at its parent. The parent of the root
+    /// directory is the root directory.
+    pub fn read_dir_with_dot_entries<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<impl Iterator<Item = DirEntry<'_>>, BackhandError> {
+        self.inner_read_dir(path.as_ref(), true)
+    }
+
+    /// Same as [`Self::read_dir`], but also returning the type and inode number of each entry
+    ///
+    /// The inode number is the one resolved from the directory entry, so entries with the same
+    /// inode number are hard links. Nodes added to [`Self::root`] after reading the image have
+    /// inode number `0`.
+    pub fn read_dir_entries_with_inode_numbers<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<impl Iterator<Item = InodeDirEntry<'_>>, BackhandError> {
+        let path = normalize_squashfs_path(path.as_ref())?;
+        let (index, dir) = self.node_with_index(&path)?;
+        if !matches!(dir.inner, InnerNode::Dir(_)) {
+            return Err(BackhandError::NotADirectory);
+        }
+
+        let entries = self
+            .root
+            .children_of(NonZeroUsize::new(index + 1).unwrap())
+            .filter(move |(_, node)| node.fullpath.parent() == Some(dir.fullpath.as_path()))
+            .filter_map(move |(_, node)| {
+                Some(InodeDirEntry {
+                    name: node.fullpath.file_name()?,
+                    node,
+                    node_type: NodeType::from(&node.inner),
+                    inode_number: node.inode_number.unwrap_or(0),
+                })
+            });
+        Ok(entries)
+    }
+
+    /// Returns `true` if the directory at `path` has no entries
+    ///
+    /// Uses the size of the directory listing stored in the inode, without looking at the entries.
+    /// Returns [`BackhandError::NotADirectory`] if `path` is not a directory.
+    pub fn is_empty_dir<P: AsRef<Path>>(&self, path: P) -> Result<bool, BackhandError> {
+        let path = normalize_squashfs_path(path.as_ref())?;
+        let index = self
+            .root
+            .nodes
+            .binary_search_by(|node| node.fullpath.cmp(&path))
+            .map_err(|_| BackhandError::FileNotFound)?;
+        if !matches!(self.root.nodes[index].inner, InnerNode::Dir(_)) {
+            return Err(BackhandError::NotADirectory);
+        }
+
+        match self.inode(&self.root.nodes[index]).map(Inode::as_dir) {
+            // the stored size is 3 more than the size of the listing
+            Some(Ok(dir)) => Ok(dir.file_size() < 4),
+            // not as read from the image, the next node would be the first entry
+            _ => Ok(!matches!(
+                self.root.nodes.get(index + 1),
+                Some(next) if next.fullpath.starts_with(&path)
+            )),
+        }
+    }
+
+    fn inner_read_dir(
+        &self,
+        path: &Path,
+        dot_entries: bool,
+    ) -> Result<impl Iterator<Item = DirEntry<'_>>, BackhandError> {
+        let path = normalize_squashfs_path(path)?;
+        let index = self
+            .root
+            .nodes
+            .binary_search_by(|node| node.fullpath.cmp(&path))
+            .map_err(|_| BackhandError::FileNotFound)?;
+        let dir = &self.root.nodes[index];
+        if !matches!(dir.inner, InnerNode::Dir(_)) {
+            return Err(BackhandError::NotADirectory);
+        }
+
+        let mut dots = vec![];
+        if dot_entries {
+            let parent = dir
+                .fullpath
+                .parent()
+                .and_then(|parent| self.root.node_by_path(parent))
+                .unwrap_or(dir);
+            dots.push(DirEntry { name: OsStr::new("."), node: dir });
+            dots.push(DirEntry { name: OsStr::new(".."), node: parent });
+        }
+
+        let children = self
+            .root
+            .children_of(NonZeroUsize::new(index + 1).unwrap())
+            .filter(move |(_, node)| node.fullpath.parent() == Some(dir.fullpath.as_path()))
+            .filter_map(|(_, node)| Some(DirEntry { name: node.fullpath.file_name()?, node }));
+        Ok(dots.into_iter().chain(children))
+    }
+
     /// Return a file handler for this file
     pub fn file<'a>(&'a self, basic_file: &'a BasicFile) -> FilesystemReaderFile<'a, 'b> {
         FilesystemReaderFile::new(self, basic_file)
     }
 
+    /// Read the file at `path` into a [`String`], following symlinks
+    ///
+    /// Returns [`BackhandError::FileNotFound`] if missing, [`BackhandError::NotAFile`] if not a
+    /// regular file, and [`BackhandError::StringUtf8`] if not valid UTF-8, see
+    /// [`std::string::FromUtf8Error::utf8_error`] for the offset of the invalid bytes.
+    pub fn read_to_string<P: AsRef<Path>>(&self, path: P) -> Result<String, BackhandError> {
+        let path = self.canonicalize(path)?;
+        let node = self.root.node_by_path(path).ok_or(BackhandError::FileNotFound)?;
+        let InnerNode::File(file) = &node.inner else {
+            return Err(BackhandError::NotAFile);
+        };
+
+        let (mut buf_read, mut buf_decompress) = self.alloc_read_buffers();
+        let mut bytes = Vec::with_capacity(self.data_capacity(&file.basic));
+        self.file(&file.basic)
+            .reader(&mut buf_read, &mut buf_decompress)
+            .read_to_end(&mut bytes)?;
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    /// Read all data of `basic_file` using `reader` instead of the internal reader
+    ///
+    /// `reader` must contain the same bytes as the image `self` was read from, with position `0`
+    /// being the start of the squashfs image (after any offset). This allows multiple threads to
+    /// each use their own file handle, without contending on the internal reader.
+    pub fn read_file_with<R: Read + Seek + ?Sized>(
+        &self,
+        basic_file: &BasicFile,
+        reader: &mut R,
+    ) -> Result<Vec<u8>, BackhandError> {
+        let file = self.file(basic_file);
+        let raw_data = file.raw_data_reader();
+
+        let mut bytes = Vec::with_capacity(self.data_capacity(basic_file));
+        let mut buf_read = Vec::with_capacity(self.block_size as usize);
+        let mut buf_decompress = vec![];
+        let mut pos = u64::from(basic_file.blocks_start);
+        for block in file {
+            let (start, size, raw_block) = match block {
+                BlockFragment::Block(block) => {
+                    let start = pos;
+                    pos += u64::from(block.size());
+                    let raw_block =
+                        RawDataBlock { fragment: false, uncompressed: block.uncompressed() };
+                    (start, block.size(), raw_block)
+                }
+                BlockFragment::Fragment(fragment) => {
+                    let raw_block =
+                        RawDataBlock { fragment: true, uncompressed: fragment.size.uncompressed() };
+                    (fragment.start, fragment.size.size(), raw_block)
+                }
+            };
+            buf_read.resize(size as usize, 0);
+            reader.seek(SeekFrom::Start(start))?;
+            reader.read_exact(&mut buf_read)?;
+
+            buf_decompress.clear();
+            raw_data.decompress(raw_block, &mut buf_read, &mut buf_decompress)?;
+            bytes.extend_from_slice(&buf_decompress);
+        }
+        bytes.truncate(basic_file.file_size as usize);
+
+        Ok(bytes)
+    }
+
+    /// Read all data of a file from its `layout`, from [`FilesystemReaderFile::layout`]
+    ///
+    /// The layout is checked against the size of the image and the block size before reading,
+    /// returning [`BackhandError::InvalidBlockLayout`] if it couldn't belong to this image.
+    pub fn read_file_from_layout(&self, layout: &BlockLayout) -> Result<Vec<u8>, BackhandError> {
+        self.check_layout(layout)?;
+
+        let mut bytes = Vec::with_capacity(layout.file_size as usize);
+        let mut buf_read = Vec::with_capacity(self.block_size as usize);
+        let mut buf_decompress = vec![];
+        let mut pos = layout.blocks_start;
+        for block in &layout.block_sizes {
+            if block.size == 0 {
+                // sparse block
+                bytes.resize(bytes.len() + self.block_size as usize, 0);
+                continue;
+            }
+            self.read_layout_block(pos, *block, &mut buf_read, &mut buf_decompress)?;
+            pos += u64::from(block.size);
+            bytes.extend_from_slice(&buf_decompress);
+        }
+        if let Some((fragment, offset)) = layout.fragment {
+            self.read_layout_block(
+                fragment.start,
+                BlockSize::from(fragment.size),
+                &mut buf_read,
+                &mut buf_decompress,
+            )?;
+            let tail = (layout.file_size % u64::from(self.block_size)) as usize;
+            let range = offset as usize..offset as usize + tail;
+            let data = buf_decompress.get(range).ok_or(BackhandError::InvalidBlockLayout)?;
+            bytes.extend_from_slice(data);
+        }
+        if bytes.len() < layout.file_size as usize {
+            return Err(BackhandError::InvalidBlockLayout);
+        }
+        bytes.truncate(layout.file_size as usize);
+
+        Ok(bytes)
+    }
+
+    pub(crate) fn check_layout(&self, layout: &BlockLayout) -> Result<(), BackhandError> {
+        let block_size = u64::from(self.block_size);
+        let block_count = layout.block_sizes.len() as u64;
+        let full_blocks = layout.file_size / block_size;
+        let valid_count = match layout.fragment {
+            // the fragment holds the part after the last full block
+            Some(_) => block_count == full_blocks,
+            None => block_count == full_blocks + u64::from(layout.file_size % block_size != 0),
+        };
+        if !valid_count {
+            return Err(BackhandError::InvalidBlockLayout);
+        }
+
+        let mut end = Some(layout.blocks_start);
+        for block in &layout.block_sizes {
+            if u64::from(block.size) > block_size {
+                return Err(BackhandError::InvalidBlockLayout);
+            }
+            end = end.and_then(|end| end.checked_add(u64::from(block.size)));
+        }
+        if !matches!(end, Some(end) if end <= self.bytes_used) {
+            return Err(BackhandError::InvalidBlockLayout);
+        }
+
+        if let Some((fragment, offset)) = layout.fragment {
+            let fragment_end = fragment.start.checked_add(u64::from(fragment.size.size()));
+            let tail = layout.file_size % block_size;
+            if !matches!(fragment_end, Some(end) if end <= self.bytes_used)
+                || u64::from(fragment.size.size()) > block_size
+                || u64::from(offset) + tail > block_size
+            {
+                return Err(BackhandError::InvalidBlockLayout);
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the block at `pos` and decompress it into `buf_decompress`
+    pub(crate) fn read_layout_block(
+        &self,
+        pos: u64,
+        block: BlockSize,
+        buf_read: &mut Vec<u8>,
+        buf_decompress: &mut Vec<u8>,
+    ) -> Result<(), BackhandError> {
+        buf_read.resize(block.size as usize, 0);
+        {
+            let mut reader = self.reader.lock().unwrap();
+            reader.seek(SeekFrom::Start(pos))?;
+            reader.read_exact(buf_read)?;
+        }
+
+        buf_decompress.clear();
+        if block.uncompressed {
+            buf_decompress.extend_from_slice(buf_read);
+            return Ok(());
+        }
+        // lzo and zstd only decompress into the allocated capacity
+        buf_decompress.reserve(self.block_size as usize);
+        match &self.decompress_pool {
+            Some(pool) => pool.decompress(&self.kind, self.compressor, buf_read, buf_decompress),
+            None => {
+                self.kind.inner.compressor.decompress(buf_read, buf_decompress, self.compressor)
+            }
+        }
+    }
+
+    /// Compare the data of `basic_file` against the data read from `other`
+    ///
+    /// Both are read one block at a time, returning `Ok(false)` on the first difference.
+    pub fn file_matches<R: Read + ?Sized>(
+        &self,
+        basic_file: &BasicFile,
+        other: &mut R,
+    ) -> Result<bool, BackhandError> {
+        let (mut buf_read, mut buf_decompress) = self.alloc_read_buffers();
+        let mut reader = self.file(basic_file).reader(&mut buf_read, &mut buf_decompress);
+
+        let mut ours = vec![0u8; self.block_size as usize];
+        let mut theirs = vec![0u8; self.block_size as usize];
+        loop {
+            let ours_len = read_full(&mut reader, &mut ours)?;
+            let theirs_len = read_full(other, &mut theirs)?;
+            if ours[..ours_len] != theirs[..theirs_len] {
+                return Ok(false);
+            }
+            if ours_len == 0 {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Write the logical content of the image into `out`
+    ///
+    /// Nodes are visited in path order, writing the path, type, permissions, uid, gid, and file
+    /// data, symlink target, or device number of each. Modification times and the on-disk layout
+    /// are not included, so images with the same contents but built with a different compressor or
+    /// at a different time write the same bytes. Writing into a cryptographic digest, such as
+    /// `sha2::Sha256` implementing [`Write`], gives a digest usable for integrity checks.
+    pub fn write_content<W: Write>(&self, out: &mut W) -> Result<(), BackhandError> {
+        let (mut buf_read, mut buf_decompress) = self.alloc_read_buffers();
+        let mut buf = vec![0u8; self.block_size as usize];
+        for node in self.files() {
+            let path = node.fullpath.as_os_str().as_bytes();
+            out.write_all(&(path.len() as u64).to_le_bytes())?;
+            out.write_all(path)?;
+            out.write_all(&node.header.permissions.to_le_bytes())?;
+            out.write_all(&node.header.uid.to_le_bytes())?;
+            out.write_all(&node.header.gid.to_le_bytes())?;
+
+            match &node.inner {
+                InnerNode::Dir(_) => out.write_all(&[0])?,
+                InnerNode::File(file) => {
+                    out.write_all(&[1])?;
+                    out.write_all(&u64::from(file.basic.file_size).to_le_bytes())?;
+                    let mut reader =
+                        self.file(&file.basic).reader(&mut buf_read, &mut buf_decompress);
+                    loop {
+                        let n = read_full(&mut reader, &mut buf)?;
+                        if n == 0 {
+                            break;
+                        }
+                        out.write_all(&buf[..n])?;
+                    }
+                }
+                InnerNode::Symlink(symlink) => {
+                    out.write_all(&[2])?;
+                    let link = symlink.link.as_os_str().as_bytes();
+                    out.write_all(&(link.len() as u64).to_le_bytes())?;
+                    out.write_all(link)?;
+                }
+                InnerNode::CharacterDevice(device) => {
+                    out.write_all(&[3])?;
+                    out.write_all(&device.device_number.to_le_bytes())?;
+                }
+                InnerNode::BlockDevice(device) => {
+                    out.write_all(&[4])?;
+                    out.write_all(&device.device_number.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Feed [`Self::write_content`] into `hasher`, returning [`Hasher::finish`]
+    ///
+    /// Only a 64 bit fingerprint for quickly comparing images, for example with
+    /// [`std::collections::hash_map::DefaultHasher`]. It must not be used for integrity checks,
+    /// colliding images are easily crafted. Use [`Self::write_content`] with a cryptographic
+    /// digest instead.
+    pub fn content_fingerprint<H: Hasher>(&self, hasher: &mut H) -> Result<u64, BackhandError> {
+        struct HasherWriter<'a, H>(&'a mut H);
+        impl<H: Hasher> Write for HasherWriter<'_, H> {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                Hasher::write(self.0, buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        self.write_content(&mut HasherWriter(hasher))?;
+        Ok(hasher.finish())
+    }
+
+    /// All character and block devices, with their major and minor numbers
+    pub fn device_nodes(&self) -> Vec<DeviceNodeInfo> {
+        self.files()
+            .filter_map(|node| {
+                let (kind, major, minor) = match &node.inner {
+                    InnerNode::CharacterDevice(dev) => {
+                        (DeviceKind::Character, dev.major(), dev.minor())
+                    }
+                    InnerNode::BlockDevice(dev) => (DeviceKind::Block, dev.major(), dev.minor()),
+                    _ => return None,
+                };
+                Some(DeviceNodeInfo {
+                    path: node.fullpath.clone(),
+                    kind,
+                    major,
+                    minor,
+                    mode: node.header.permissions,
+                })
+            })
+            .collect()
+    }
+
     /// Iterator of all files, including the root
     ///
     /// # Example
@@ -166,6 +966,193 @@ impl<'b> FilesystemReader<'b> {
     pub fn files(&self) -> impl Iterator<Item = &Node<SquashfsFileReader>> {
         self.root.nodes.iter()
     }
+
+    /// Iterator of all nodes, including the root. Same as [`Self::files`]
+    pub fn iter(&self) -> impl Iterator<Item = &Node<SquashfsFileReader>> {
+        self.files()
+    }
+
+    /// Iterator of the path and inode of all nodes, including the root
+    ///
+    /// The inode exposes the details not part of [`Node`], such as the block sizes and xattr
+    /// index. Nodes without an inode, such as added to [`Self::root`] after reading the image,
+    /// are skipped.
+    pub fn iter_with_inodes(&self) -> impl Iterator<Item = (&Path, &Inode)> {
+        self.root.nodes.iter().filter_map(|node| Some((node.fullpath.as_path(), self.inode(node)?)))
+    }
+
+    /// Paths of the nodes sharing an inode, for recreating hard links
+    ///
+    /// Each group holds at least two paths, in the order of [`Self::files`], and the groups are
+    /// ordered by their first path. The link count of the inode is
+    /// [`NodeMetadata::nlink`](crate::NodeMetadata::nlink). Directories are never included, as
+    /// each is only read once, and neither are nodes without an inode.
+    pub fn hard_link_groups(&self) -> Vec<Vec<&Path>> {
+        let mut groups: Vec<Vec<&Path>> = vec![];
+        let mut group_of_inode = FxHashMap::default();
+        for node in &self.root.nodes {
+            if matches!(node.inner, InnerNode::Dir(_)) {
+                continue;
+            }
+            let Some(inode_num) = node.inode_number else {
+                continue;
+            };
+            let index = *group_of_inode.entry(inode_num).or_insert_with(|| {
+                groups.push(vec![]);
+                groups.len() - 1
+            });
+            groups[index].push(node.fullpath.as_path());
+        }
+        groups.retain(|group| group.len() > 1);
+        groups
+    }
+
+    /// Depth first iterator of all nodes, each directory before its contents
+    ///
+    /// The root comes first, and the entries of each directory are ordered by name. This is the
+    /// same order as [`Self::files`].
+    pub fn walk_dfs_pre(&self) -> impl Iterator<Item = &Node<SquashfsFileReader>> {
+        self.root.nodes.iter()
+    }
+
+    /// Depth first iterator of all nodes, each directory after its contents
+    ///
+    /// The root comes last, and the entries of each directory are ordered by name.
+    pub fn walk_dfs_post(&self) -> impl Iterator<Item = &Node<SquashfsFileReader>> {
+        let mut post = Vec::with_capacity(self.root.nodes.len());
+        // directories not yet finished, from the root down to the current node
+        let mut open: Vec<&Node<SquashfsFileReader>> = vec![];
+        for node in &self.root.nodes {
+            while let Some(parent) = open.pop() {
+                if node.fullpath.starts_with(&parent.fullpath) {
+                    open.push(parent);
+                    break;
+                }
+                post.push(parent);
+            }
+            open.push(node);
+        }
+        post.extend(open.into_iter().rev());
+        post.into_iter()
+    }
+
+    /// Breadth first iterator of all nodes, all nodes of a depth before any of the next depth
+    ///
+    /// The root comes first. Within a depth, nodes are grouped by their directory, in the order
+    /// the directories were visited, and ordered by name.
+    pub fn walk_bfs(&self) -> impl Iterator<Item = &Node<SquashfsFileReader>> {
+        let mut nodes: Vec<_> = self.root.nodes.iter().collect();
+        // stable, so the depth first order is kept within a depth
+        nodes.sort_by_key(|node| node.fullpath.components().count());
+        nodes.into_iter()
+    }
+
+    /// Number of nodes, including the root
+    pub fn len(&self) -> usize {
+        self.root.nodes.len()
+    }
+
+    /// Returns `true` if there are no nodes, not even the root
+    pub fn is_empty(&self) -> bool {
+        self.root.nodes.is_empty()
+    }
+
+    /// Write the contents of `self` as a new image into `w`
+    ///
+    /// Same as [`FilesystemWriter::from_fs_reader`] followed by [`FilesystemWriter::write`],
+    /// use those directly to change the contents before writing. The node headers, id table,
+    /// block size, compressor, and `mod_time` are kept, and the data blocks of files are copied
+    /// without decompressing them. The output only depends on the contents, so writing an image
+    /// read from this output again gives identical bytes.
+    ///
+    /// Extended attributes can't be written, this fails with [`BackhandError::XattrsNotWritten`]
+    /// before writing anything if a node has them. Use [`FilesystemWriter::from_fs_reader`] to
+    /// write the image without them.
+    ///
+    /// # Returns
+    /// (written populated [`SuperBlock`], total amount of bytes written including padding)
+    pub fn write_to<W: Write + Seek>(&self, w: &mut W) -> Result<(SuperBlock, u64), BackhandError> {
+        if let Some(node) = self.root.nodes.iter().find(|node| self.xattrs(node).is_some()) {
+            return Err(BackhandError::XattrsNotWritten(node.fullpath.clone()));
+        }
+        FilesystemWriter::from_fs_reader(self)?.write(w)
+    }
+}
+
+impl<'a, 'b> IntoIterator for &'a FilesystemReader<'b> {
+    type Item = &'a Node<SquashfsFileReader>;
+    type IntoIter = std::slice::Iter<'a, Node<SquashfsFileReader>>;
+
+    /// Same as [`FilesystemReader::iter`]
+    fn into_iter(self) -> Self::IntoIter {
+        self.root.nodes.iter()
+    }
+}
+
+/// Entry of a directory, returned by [`FilesystemReader::read_dir`]
+#[derive(Debug, Clone, Copy)]
+pub struct DirEntry<'a> {
+    /// File name of the entry
+    pub name: &'a OsStr,
+    /// Node the entry points to
+    pub node: &'a Node<SquashfsFileReader>,
+}
+
+/// Entry of a directory, returned by [`FilesystemReader::read_dir_entries_with_inode_numbers`]
+#[derive(Debug, Clone, Copy)]
+pub struct InodeDirEntry<'a> {
+    /// File name of the entry
+    pub name: &'a OsStr,
+    /// Node the entry points to
+    pub node: &'a Node<SquashfsFileReader>,
+    /// Type of the node
+    pub node_type: NodeType,
+    /// Inode number the entry points to
+    pub inode_number: u32,
+}
+
+/// Order of the entries returned by [`FilesystemReader::read_dir_sorted`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DirOrder {
+    /// By name, same as the order stored in the image
+    #[default]
+    NameAsc,
+    /// By name, reversed
+    NameDesc,
+    /// Smallest files first, everything other than files has size 0
+    SizeAsc,
+    /// Largest files first, everything other than files has size 0
+    SizeDesc,
+    /// Oldest modification time first
+    MtimeAsc,
+    /// Directories, then everything else
+    DirsFirst,
+}
+
+/// Read into `buf` until it's full or `reader` reaches EOF, returning the amount of bytes read
+fn read_full<R: Read + ?Sized>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Push the components of `path` into `stack` in reverse order, so they can be popped in order
+fn push_components(stack: &mut Vec<OsString>, path: &Path) -> Result<(), BackhandError> {
+    for component in path.components().rev() {
+        match component {
+            Component::Prefix(..) => return Err(BackhandError::InvalidFilePath),
+            Component::RootDir => {}
+            c => stack.push(c.as_os_str().to_os_string()),
+        }
+    }
+    Ok(())
 }
 
 /// Filesystem handle for file
@@ -202,6 +1189,13 @@ impl<'a, 'b> FilesystemReaderFile<'a, 'b> {
         self.raw_data_reader().into_reader(buf_read, buf_decompress)
     }
 
+    /// Same as [`Self::reader`], but allocating its own buffers
+    pub fn owned_reader(&self) -> SquashfsReadFile<'a, 'b> {
+        let (buf_read, buf_decompress) = self.system.alloc_read_buffers();
+        self.raw_data_reader()
+            .into_reader_with(ReadBuffer::Owned(buf_read), ReadBuffer::Owned(buf_decompress))
+    }
+
     pub fn fragment(&self) -> Option<&'a Fragment> {
         if self.basic.frag_index == 0xffffffff {
             None
@@ -213,6 +1207,16 @@ impl<'a, 'b> FilesystemReaderFile<'a, 'b> {
         }
     }
 
+    /// Location of the data of this file, for [`FilesystemReader::read_file_from_layout`]
+    pub fn layout(&self) -> BlockLayout {
+        BlockLayout {
+            blocks_start: u64::from(self.basic.blocks_start),
+            block_sizes: self.basic.block_sizes.iter().copied().map(BlockSize::from).collect(),
+            fragment: self.fragment().map(|fragment| (*fragment, self.basic.block_offset)),
+            file_size: u64::from(self.basic.file_size),
+        }
+    }
+
     pub(crate) fn raw_data_reader(&self) -> SquashfsRawData<'a, 'b> {
         SquashfsRawData::new(Self { system: self.system, basic: self.basic })
     }
@@ -288,8 +1292,8 @@ impl<'a, 'b> SquashfsRawData<'a, 'b> {
                 Ok(RawDataBlock { fragment: false, uncompressed: block.uncompressed() })
             }
             BlockFragment::Fragment(fragment) => {
-                let cache = self.file.system.cache.lock().unwrap();
-                if let Some(cache_bytes) = cache.fragment_cache.get(&fragment.start) {
+                let mut cache = self.file.system.cache.lock().unwrap();
+                if let Some(cache_bytes) = cache.fragment(fragment.start) {
                     //if in cache, just return the cache, don't read it
                     let cache_size = cache_bytes.len();
                     data.resize(cache_size, 0);
@@ -331,17 +1335,31 @@ impl<'a, 'b> SquashfsRawData<'a, 'b> {
     ) -> Result<(), BackhandError> {
         //append to the output_buf is not allowed, it need to be empty
         assert!(output_buf.is_empty());
+        //sparse block, nothing is stored and the whole block is zeros
+        if !data.fragment && input_buf.is_empty() {
+            output_buf.resize(self.file.system.block_size as usize, 0);
+            return Ok(());
+        }
         //input is already decompress, so just swap the input/output, so the
         //output_buf contains the final data.
+        //The bit of each block is used, not the superblock flags, same as the kernel.
         if data.uncompressed {
             std::mem::swap(input_buf, output_buf);
         } else {
             output_buf.reserve(self.file.system.block_size as usize);
-            self.file.system.kind.inner.compressor.decompress(
-                input_buf,
-                output_buf,
-                self.file.system.compressor,
-            )?;
+            let system = self.file.system;
+            match &system.decompress_pool {
+                Some(pool) => {
+                    pool.decompress(&system.kind, system.compressor, input_buf, output_buf)?
+                }
+                None => {
+                    system.kind.inner.compressor.decompress(
+                        input_buf,
+                        output_buf,
+                        system.compressor,
+                    )?;
+                }
+            }
             // store the cache, so decompression is not duplicated
             if data.fragment {
                 self.file
@@ -349,8 +1367,7 @@ impl<'a, 'b> SquashfsRawData<'a, 'b> {
                     .cache
                     .lock()
                     .unwrap()
-                    .fragment_cache
-                    .insert(self.file.fragment().unwrap().start, output_buf.clone());
+                    .insert_fragment(self.file.fragment().unwrap().start, output_buf.clone());
             }
         }
         //apply the fragment offset
@@ -366,16 +1383,50 @@ impl<'a, 'b> SquashfsRawData<'a, 'b> {
         self,
         buf_read: &'a mut Vec<u8>,
         buf_decompress: &'a mut Vec<u8>,
+    ) -> SquashfsReadFile<'a, 'b> {
+        self.into_reader_with(ReadBuffer::Borrowed(buf_read), ReadBuffer::Borrowed(buf_decompress))
+    }
+
+    fn into_reader_with(
+        self,
+        buf_read: ReadBuffer<'a>,
+        buf_decompress: ReadBuffer<'a>,
     ) -> SquashfsReadFile<'a, 'b> {
         let bytes_available = self.file.basic.file_size as usize;
         SquashfsReadFile { raw_data: self, buf_read, buf_decompress, last_read: 0, bytes_available }
     }
 }
 
+/// Buffer of [`SquashfsReadFile`], given by the caller or owned
+enum ReadBuffer<'a> {
+    Borrowed(&'a mut Vec<u8>),
+    Owned(Vec<u8>),
+}
+
+impl<'a> Deref for ReadBuffer<'a> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        match self {
+            Self::Borrowed(buf) => buf,
+            Self::Owned(buf) => buf,
+        }
+    }
+}
+
+impl<'a> DerefMut for ReadBuffer<'a> {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        match self {
+            Self::Borrowed(buf) => buf,
+            Self::Owned(buf) => buf,
+        }
+    }
+}
+
 pub struct SquashfsReadFile<'a, 'b> {
     raw_data: SquashfsRawData<'a, 'b>,
-    buf_read: &'a mut Vec<u8>,
-    buf_decompress: &'a mut Vec<u8>,
+    buf_read: ReadBuffer<'a>,
+    buf_decompress: ReadBuffer<'a>,
     //offset of buf_decompress to start reading
     last_read: usize,
     bytes_available: usize,
@@ -396,12 +1447,12 @@ impl<'a, 'b> SquashfsReadFile<'a, 'b> {
     }
 
     fn read_next_block(&mut self) -> Result<(), BackhandError> {
-        let block = match self.raw_data.next_block(self.buf_read) {
+        let block = match self.raw_data.next_block(&mut self.buf_read) {
             Some(block) => block?,
             None => return Ok(()),
         };
         self.buf_decompress.clear();
-        self.raw_data.decompress(block, self.buf_read, self.buf_decompress)?;
+        self.raw_data.decompress(block, &mut self.buf_read, &mut self.buf_decompress)?;
         self.last_read = 0;
         Ok(())
     }
@@ -18,6 +18,7 @@ use crate::entry::Entry;
 use crate::error::BackhandError;
 use crate::filesystem::node::SquashfsSymlink;
 use crate::id::Id;
+use crate::inode::InodeRef;
 use crate::kind::Kind;
 use crate::kinds::LE_V4_0;
 use crate::metadata::{self, MetadataWriter, METADATA_MAXSIZE};
@@ -211,7 +212,12 @@ impl<'a, 'b> FilesystemWriter<'a, 'b> {
                     InnerNode::CharacterDevice(x) => InnerNode::CharacterDevice(*x),
                     InnerNode::BlockDevice(x) => InnerNode::BlockDevice(*x),
                 };
-                Node { fullpath: node.fullpath.clone(), header: node.header, inner }
+                Node {
+                    fullpath: node.fullpath.clone(),
+                    header: node.header,
+                    inner,
+                    inode_number: None,
+                }
             })
             .collect();
         root.sort();
@@ -645,7 +651,7 @@ impl<'a, 'b> FilesystemWriter<'a, 'b> {
             &self.id_table,
         )?;
 
-        superblock.root_inode = ((root.start as u64) << 16) | ((root.offset as u64) & 0xffff);
+        superblock.root_inode = InodeRef::new(u64::from(root.start), root.offset).0;
         superblock.inode_count = self.root.nodes.len().try_into().unwrap();
         superblock.block_size = self.block_size;
         superblock.block_log = self.block_log;
@@ -714,17 +720,7 @@ impl<'a, 'b> FilesystemWriter<'a, 'b> {
         // Seek back the beginning and write the superblock
         info!("Writing Superblock");
         w.rewind()?;
-        let mut bv = BitVec::new();
-        superblock.write(
-            &mut bv,
-            (
-                self.kind.inner.magic,
-                self.kind.inner.version_major,
-                self.kind.inner.version_minor,
-                self.kind.inner.type_endian,
-            ),
-        )?;
-        w.write_all(bv.as_raw_slice())?;
+        w.write_all(&superblock.to_bytes(&self.kind)?)?;
 
         info!("Writing Finished");
 
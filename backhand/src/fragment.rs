@@ -19,4 +19,47 @@ impl Fragment {
     pub fn new(start: u64, size: DataSize, unused: u32) -> Self {
         Self { start, size, unused }
     }
+
+    /// Returns `true` if the fragment block is stored compressed
+    pub fn is_compressed(&self) -> bool {
+        !self.size.uncompressed()
+    }
+
+    /// Size of the fragment block as stored in the image
+    pub fn block_size(&self) -> u32 {
+        self.size.size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deku::bitvec::{BitView, Msb0};
+
+    use super::*;
+
+    #[test]
+    fn decode_fragment() {
+        #[rustfmt::skip]
+        let bytes = [
+            // start
+            0x60, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // size, with the uncompressed bit
+            0x34, 0x12, 0x00, 0x01,
+            // unused
+            0x00, 0x00, 0x00, 0x00,
+        ];
+        let (rest, fragment) =
+            Fragment::read(bytes.view_bits::<Msb0>(), deku::ctx::Endian::Little).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(fragment.start, 0x10060);
+        assert!(!fragment.is_compressed());
+        assert_eq!(fragment.block_size(), 0x1234);
+        assert_eq!(fragment.size.raw(), 0x0100_1234);
+
+        // compressed
+        let fragment = Fragment::new(0x60, DataSize::new_compressed(0x1234), 0);
+        assert!(fragment.is_compressed());
+        assert_eq!(fragment.block_size(), 0x1234);
+        assert_eq!(fragment.size.raw(), 0x1234);
+    }
 }
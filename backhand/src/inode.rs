@@ -2,6 +2,7 @@
 
 use core::fmt;
 use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use deku::bitvec::{BitVec, Msb0};
 use deku::prelude::*;
@@ -9,10 +10,34 @@ use deku::prelude::*;
 use crate::data::DataSize;
 use crate::dir::DirectoryIndex;
 use crate::entry::Entry;
+use crate::error::BackhandError;
 use crate::kind::Kind;
 use crate::metadata::MetadataWriter;
 use crate::squashfs::SuperBlock;
 
+/// Reference to the location of an inode in the inode table
+///
+/// The upper 48 bits store the start of the metadata block relative to the inode table, the lower
+/// 16 bits store the offset into the uncompressed metadata block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InodeRef(pub u64);
+
+impl InodeRef {
+    pub fn new(block_start: u64, offset: u16) -> Self {
+        Self((block_start << 16) | u64::from(offset))
+    }
+
+    /// Start of the metadata block, relative to the start of the inode table
+    pub fn block_start(&self) -> u64 {
+        self.0 >> 16
+    }
+
+    /// Offset into the uncompressed metadata block
+    pub fn offset(&self) -> u16 {
+        (self.0 & 0xffff) as u16
+    }
+}
+
 #[derive(Debug, DekuRead, DekuWrite, Clone, PartialEq, Eq)]
 #[deku(ctx = "bytes_used: u64, block_size: u32, block_log: u16, type_endian: deku::ctx::Endian")]
 #[deku(endian = "type_endian")]
@@ -28,6 +53,63 @@ impl Inode {
         Inode { id, header, inner }
     }
 
+    /// Fields of both basic and extended inodes, normalized into one shape
+    pub fn metadata(&self) -> InodeMetadata {
+        let mut metadata = InodeMetadata {
+            id: self.id,
+            header: self.header,
+            extended: false,
+            link_count: 1,
+            file_size: None,
+            parent_inode: None,
+            xattr_index: None,
+            sparse: None,
+            dir_index_count: None,
+        };
+        match &self.inner {
+            InodeInner::BasicDirectory(dir) => {
+                metadata.link_count = dir.link_count;
+                metadata.file_size = Some(u64::from(dir.file_size));
+                metadata.parent_inode = Some(dir.parent_inode);
+            }
+            InodeInner::BasicFile(file) => {
+                metadata.file_size = Some(u64::from(file.file_size));
+            }
+            InodeInner::BasicSymlink(symlink) => {
+                metadata.link_count = symlink.link_count;
+                metadata.file_size = Some(u64::from(symlink.target_size));
+            }
+            InodeInner::BasicBlockDevice(device) | InodeInner::BasicCharacterDevice(device) => {
+                metadata.link_count = device.link_count;
+            }
+            InodeInner::ExtendedDirectory(dir) => {
+                metadata.extended = true;
+                metadata.link_count = dir.link_count;
+                metadata.file_size = Some(u64::from(dir.file_size));
+                metadata.parent_inode = Some(dir.parent_inode);
+                metadata.xattr_index = xattr_index(dir.xattr_index);
+                metadata.dir_index_count = Some(dir.index_count);
+            }
+            InodeInner::ExtendedFile(file) => {
+                metadata.extended = true;
+                metadata.link_count = file.link_count;
+                metadata.file_size = Some(file.file_size);
+                metadata.xattr_index = xattr_index(file.xattr_index);
+                metadata.sparse = Some(file.sparse);
+            }
+        }
+        metadata
+    }
+
+    /// Return the directory details, or error if this isn't a directory
+    pub fn as_dir(&self) -> Result<DirectoryInode<'_>, BackhandError> {
+        match &self.inner {
+            InodeInner::BasicDirectory(dir) => Ok(DirectoryInode::Basic(dir)),
+            InodeInner::ExtendedDirectory(dir) => Ok(DirectoryInode::Extended(dir)),
+            _ => Err(BackhandError::UnexpectedInode(self.inner.clone())),
+        }
+    }
+
     /// Write to `m_writer`, creating Entry
     pub(crate) fn to_bytes<'a>(
         &self,
@@ -62,6 +144,35 @@ impl Inode {
     }
 }
 
+/// Details of any [`Inode`], returned by [`Inode::metadata`]
+///
+/// Fields only stored by some inode types are `None` for the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InodeMetadata {
+    /// Type of the inode, including if basic or extended
+    pub id: InodeId,
+    pub header: InodeHeader,
+    /// `true` for the extended inode types
+    pub extended: bool,
+    /// Hard link count. Basic files don't store this, and use `1`
+    pub link_count: u32,
+    /// Size of the file data, directory listing, or symlink target
+    pub file_size: Option<u64>,
+    /// Inode number of the parent directory, for directories
+    pub parent_inode: Option<u32>,
+    /// Index into the xattr table, for extended inodes with xattrs
+    pub xattr_index: Option<u32>,
+    /// Bytes saved by sparse blocks, for extended files
+    pub sparse: Option<u64>,
+    /// Count of directory index entries, for extended directories
+    pub dir_index_count: Option<u16>,
+}
+
+/// Extended inodes without xattrs store `0xffffffff`
+fn xattr_index(index: u32) -> Option<u32> {
+    (index != 0xffff_ffff).then_some(index)
+}
+
 #[derive(Debug, DekuRead, DekuWrite, Clone, Copy, PartialEq, Eq)]
 #[deku(type = "u16")]
 #[deku(endian = "endian", ctx = "endian: deku::ctx::Endian")]
@@ -133,6 +244,62 @@ pub struct InodeHeader {
     pub inode_number: u32,
 }
 
+impl InodeHeader {
+    /// `mtime` interpreted as a [`SystemTime`]
+    ///
+    /// `mtime` is unsigned seconds since the unix epoch, so this is never before 1970.
+    pub fn modified_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(u64::from(self.mtime))
+    }
+}
+
+/// Either a [`BasicDirectory`] or [`ExtendedDirectory`], returned from [`Inode::as_dir`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirectoryInode<'a> {
+    Basic(&'a BasicDirectory),
+    Extended(&'a ExtendedDirectory),
+}
+
+impl<'a> DirectoryInode<'a> {
+    /// Start of the metadata block, relative to the start of the directory table
+    pub fn block_index(&self) -> u32 {
+        match self {
+            Self::Basic(dir) => dir.block_index,
+            Self::Extended(dir) => dir.block_index,
+        }
+    }
+
+    /// Total uncompressed size of the directory listing, including headers
+    pub fn file_size(&self) -> u32 {
+        match self {
+            Self::Basic(dir) => u32::from(dir.file_size),
+            Self::Extended(dir) => dir.file_size,
+        }
+    }
+
+    /// Offset into the uncompressed metadata block
+    pub fn block_offset(&self) -> u16 {
+        match self {
+            Self::Basic(dir) => dir.block_offset,
+            Self::Extended(dir) => dir.block_offset,
+        }
+    }
+
+    pub fn link_count(&self) -> u32 {
+        match self {
+            Self::Basic(dir) => dir.link_count,
+            Self::Extended(dir) => dir.link_count,
+        }
+    }
+
+    pub fn parent_inode(&self) -> u32 {
+        match self {
+            Self::Basic(dir) => dir.parent_inode,
+            Self::Extended(dir) => dir.parent_inode,
+        }
+    }
+}
+
 #[derive(Debug, DekuRead, DekuWrite, Clone, PartialEq, Eq)]
 #[deku(endian = "endian", ctx = "endian: deku::ctx::Endian")]
 pub struct BasicDirectory {
@@ -232,8 +399,11 @@ impl fmt::Debug for BasicSymlink {
     }
 }
 impl BasicSymlink {
+    /// Target of the symlink, with invalid UTF-8 replaced by `U+FFFD`
+    ///
+    /// Use `target_path` for the exact bytes.
     pub fn target(&self) -> String {
-        std::str::from_utf8(&self.target_path).unwrap().to_string()
+        String::from_utf8_lossy(&self.target_path).into_owned()
     }
 }
 
@@ -243,3 +413,63 @@ pub struct BasicDeviceSpecialFile {
     pub link_count: u32,
     pub device_number: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata() {
+        let header = InodeHeader { permissions: 0o644, uid: 0, gid: 0, mtime: 0, inode_number: 2 };
+        let basic = BasicFile {
+            blocks_start: 0x60,
+            frag_index: 0xffff_ffff,
+            block_offset: 0,
+            file_size: 10,
+            block_sizes: vec![],
+        };
+        let inode = Inode::new(InodeId::BasicFile, header, InodeInner::BasicFile(basic));
+        let metadata = inode.metadata();
+        assert!(!metadata.extended);
+        assert_eq!(metadata.link_count, 1);
+        assert_eq!(metadata.file_size, Some(10));
+        assert_eq!(metadata.xattr_index, None);
+        assert_eq!(metadata.sparse, None);
+
+        let extended = ExtendedFile {
+            blocks_start: 0x60,
+            file_size: 0x10_0000_0000,
+            sparse: 0x1000,
+            link_count: 3,
+            frag_index: 0xffff_ffff,
+            block_offset: 0,
+            xattr_index: 7,
+            block_sizes: vec![],
+        };
+        let inode = Inode::new(InodeId::ExtendedFile, header, InodeInner::ExtendedFile(extended));
+        let metadata = inode.metadata();
+        assert!(metadata.extended);
+        assert_eq!(metadata.id, InodeId::ExtendedFile);
+        assert_eq!(metadata.link_count, 3);
+        assert_eq!(metadata.file_size, Some(0x10_0000_0000));
+        assert_eq!(metadata.xattr_index, Some(7));
+        assert_eq!(metadata.sparse, Some(0x1000));
+
+        let dir = ExtendedDirectory {
+            link_count: 2,
+            file_size: 3,
+            block_index: 0,
+            parent_inode: 1,
+            index_count: 0,
+            block_offset: 0,
+            xattr_index: 0xffff_ffff,
+            dir_index: vec![],
+        };
+        let inode =
+            Inode::new(InodeId::ExtendedDirectory, header, InodeInner::ExtendedDirectory(dir));
+        let metadata = inode.metadata();
+        assert_eq!(metadata.parent_inode, Some(1));
+        assert_eq!(metadata.xattr_index, None);
+        assert_eq!(metadata.dir_index_count, Some(0));
+    }
+}
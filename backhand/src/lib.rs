@@ -12,6 +12,11 @@
 //! For reading an image and extracting its details and contents, use
 //! [`FilesystemReader::from_reader`].
 //!
+//! To extract an image to disk in one call, use [`extract`].
+//! With the `tar` feature, `FilesystemReader::to_tar` converts it into a tar archive instead.
+//! With the `memmap` feature, `FilesystemReader::extract_file_mmap` extracts a single large file
+//! through a memory mapping.
+//!
 //! ### Writing
 //! For creating a modified or new image, use [`FilesystemWriter::from_fs_reader`].
 //! [`FilesystemWriter`] can also be created from scratch, without a previous image to base itself
@@ -55,39 +60,59 @@
 #[doc = include_str!("../../README.md")]
 type _ReadmeTest = ();
 
+mod advise;
+#[cfg(feature = "tar")]
+mod archive;
 mod compressor;
 mod data;
 mod dir;
 mod entry;
 mod error;
 mod export;
+mod extract;
 mod filesystem;
 mod fragment;
 mod id;
 mod inode;
 mod kinds;
-mod metadata;
+pub mod metadata;
+#[cfg(feature = "memmap")]
+mod mmap;
 mod reader;
 mod squashfs;
+mod verify;
+mod xattr;
 
-pub use crate::data::DataSize;
+pub use crate::data::{BlockLayout, BlockSize, DataSize};
+pub use crate::dir::{DirIndexEntry, ReadDirEntry};
 pub use crate::error::BackhandError;
 pub use crate::export::Export;
+pub use crate::extract::{extract, ExtractOptions, ExtractReport};
 pub use crate::filesystem::node::{
-    InnerNode, Node, NodeHeader, SquashfsBlockDevice, SquashfsCharacterDevice, SquashfsDir,
-    SquashfsFileReader, SquashfsFileWriter, SquashfsSymlink,
+    DeviceKind, DeviceNodeInfo, InnerNode, Node, NodeHeader, NodeMetadata, NodeType,
+    SquashfsBlockDevice, SquashfsCharacterDevice, SquashfsDir, SquashfsFileReader,
+    SquashfsFileWriter, SquashfsSymlink,
+};
+pub use crate::filesystem::reader::{
+    DirEntry, DirOrder, FilesystemReader, FilesystemReaderFile, InodeDirEntry, SquashfsReadFile,
 };
-pub use crate::filesystem::reader::{FilesystemReader, FilesystemReaderFile, SquashfsReadFile};
 pub use crate::filesystem::writer::{
     CompressionExtra, ExtraXz, FilesystemCompressor, FilesystemWriter,
 };
 pub use crate::fragment::Fragment;
 pub use crate::id::Id;
-pub use crate::inode::{BasicFile, Inode};
+pub use crate::inode::{
+    BasicDeviceSpecialFile, BasicDirectory, BasicFile, BasicSymlink, ExtendedDirectory,
+    ExtendedFile, Inode, InodeHeader, InodeId, InodeInner, InodeMetadata, InodeRef,
+};
 pub use crate::reader::BufReadSeek;
 pub use crate::squashfs::{
-    Squashfs, SuperBlock, DEFAULT_BLOCK_SIZE, DEFAULT_PAD_LEN, MAX_BLOCK_SIZE, MIN_BLOCK_SIZE,
+    OpenDiagnostics, ReadWarning, Squashfs, SquashfsParts, SquashfsReaderBuilder, SuperBlock,
+    TableBlockCounts, DEFAULT_BLOCK_SIZE, DEFAULT_MAX_DIR_DEPTH, DEFAULT_PAD_LEN, MAX_BLOCK_SIZE,
+    MIN_BLOCK_SIZE,
 };
+pub use crate::verify::{VerifyError, VerifyReport};
+pub use crate::xattr::{Xattr, XattrPrefix};
 
 /// Support the wonderful world of vendor formats
 pub mod kind {
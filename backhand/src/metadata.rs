@@ -1,3 +1,10 @@
+//! Metadata blocks
+//!
+//! Inodes, directories, and the fragment, export, and id tables are stored in metadata blocks.
+//! Each block starts with a 16-bit header (using the data endian of the [`Kind`]) followed by up
+//! to [`METADATA_MAXSIZE`] bytes of data. The lower 15 bits of the header are the length of the
+//! stored data, the top bit is set if the data is stored uncompressed.
+
 use std::collections::VecDeque;
 use std::io::{self, Read, Seek, Write};
 
@@ -5,11 +12,13 @@ use deku::bitvec::{BitVec, BitView};
 use deku::prelude::*;
 use tracing::trace;
 
+use crate::compressor::Compressor;
 use crate::error::BackhandError;
 use crate::filesystem::writer::FilesystemCompressor;
 use crate::kinds::Kind;
 use crate::squashfs::SuperBlock;
 
+/// Maximum uncompressed size of a metadata block
 pub const METADATA_MAXSIZE: usize = 0x2000;
 
 const METDATA_UNCOMPRESSED: u16 = 1 << 15;
@@ -114,11 +123,39 @@ impl Write for MetadataWriter {
     }
 }
 
+/// Read and decompress the metadata block at the current position of `reader`
+///
+/// The block is decompressed with the `compressor` of `superblock`, using the
+/// [`crate::compression::CompressionAction`] from `kind`.
+///
+/// Returns [`BackhandError::OversizedMetadataBlock`] if the block is larger than
+/// [`METADATA_MAXSIZE`] once decompressed.
 pub fn read_block<R: Read + ?Sized>(
     reader: &mut R,
     superblock: &SuperBlock,
     kind: &Kind,
 ) -> Result<Vec<u8>, BackhandError> {
+    read_block_with_compressor(reader, kind, superblock.compressor)
+}
+
+/// Same as [`read_block`], but decompress with an explicit `compressor`
+///
+/// Useful for images where the superblock is missing or corrupted.
+pub fn read_block_with_compressor<R: Read + ?Sized>(
+    reader: &mut R,
+    kind: &Kind,
+    compressor: Compressor,
+) -> Result<Vec<u8>, BackhandError> {
+    let (metadata_len, buf) = read_raw_block(reader, kind)?;
+    decompress_block(metadata_len, buf, kind, compressor)
+}
+
+/// Read the header and the stored bytes of the metadata block at the current position of
+/// `reader`, without decompressing them
+pub(crate) fn read_raw_block<R: Read + ?Sized>(
+    reader: &mut R,
+    kind: &Kind,
+) -> Result<(u16, Vec<u8>), BackhandError> {
     let mut buf = [0u8; 2];
     reader.read_exact(&mut buf)?;
 
@@ -130,11 +167,20 @@ pub fn read_block<R: Read + ?Sized>(
     tracing::trace!("len: 0x{:02x?}", byte_len);
     let mut buf = vec![0u8; byte_len as usize];
     reader.read_exact(&mut buf)?;
+    Ok((metadata_len, buf))
+}
 
+/// Decompress `buf`, read by [`read_raw_block`] along with its header `metadata_len`
+pub(crate) fn decompress_block(
+    metadata_len: u16,
+    buf: Vec<u8>,
+    kind: &Kind,
+    compressor: Compressor,
+) -> Result<Vec<u8>, BackhandError> {
     let bytes = if is_compressed(metadata_len) {
         tracing::trace!("compressed");
         let mut out = Vec::with_capacity(8 * 1024);
-        kind.inner.compressor.decompress(&buf, &mut out, superblock.compressor)?;
+        kind.inner.compressor.decompress(&buf, &mut out, compressor)?;
         out
     } else {
         tracing::trace!("uncompressed");
@@ -142,6 +188,9 @@ pub fn read_block<R: Read + ?Sized>(
     };
 
     tracing::trace!("uncompressed size: 0x{:02x?}", bytes.len());
+    if bytes.len() > METADATA_MAXSIZE {
+        return Err(BackhandError::OversizedMetadataBlock(bytes.len()));
+    }
     Ok(bytes)
 }
 
@@ -155,6 +204,7 @@ pub fn len(len: u16) -> u16 {
     len & !(METDATA_UNCOMPRESSED)
 }
 
+/// Set the uncompressed bit in raw `len`
 pub fn set_if_uncompressed(len: u16) -> u16 {
     len | METDATA_UNCOMPRESSED
 }
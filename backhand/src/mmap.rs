@@ -0,0 +1,88 @@
+//! Extract files into memory mapped output
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use memmap2::MmapMut;
+use tracing::{trace, warn};
+
+use crate::error::BackhandError;
+use crate::filesystem::reader::{FilesystemReader, FilesystemReaderFile};
+use crate::{InnerNode, Node, SquashfsFileReader};
+
+impl<'b> FilesystemReader<'b> {
+    /// Extract the file `node` into `dest`, writing through a memory mapping of `dest`
+    ///
+    /// `dest` is created, or truncated, with the size of the file, and each block is copied into
+    /// the mapping once decompressed. This avoids a write call per block when extracting large
+    /// files. If `dest` can't be mapped, the file is written with buffered writes instead.
+    ///
+    /// Returns [`BackhandError::NotAFile`] if `node` is not a regular file.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::fs::File;
+    /// # use std::io::BufReader;
+    /// # use std::path::Path;
+    /// # use backhand::FilesystemReader;
+    /// let file = BufReader::new(File::open("image.squashfs").unwrap());
+    /// let filesystem = FilesystemReader::from_reader(file).unwrap();
+    /// let node = filesystem.files().find(|node| node.fullpath == Path::new("/large")).unwrap();
+    /// filesystem.extract_file_mmap(node, Path::new("large")).unwrap();
+    /// ```
+    pub fn extract_file_mmap(
+        &self,
+        node: &Node<SquashfsFileReader>,
+        dest: &Path,
+    ) -> Result<(), BackhandError> {
+        let InnerNode::File(file) = &node.inner else {
+            return Err(BackhandError::NotAFile);
+        };
+        let file = self.file(&file.basic);
+        let size = u64::from(file.basic.file_size);
+        trace!("extracting {} with mmap", node.fullpath.display());
+
+        let out =
+            OpenOptions::new().read(true).write(true).create(true).truncate(true).open(dest)?;
+        out.set_len(size)?;
+        // mapping an empty file is an error, nothing to write anyway
+        if size == 0 {
+            return Ok(());
+        }
+
+        // SAFETY: the file was just created with the correct size, modifying or truncating it
+        // from another process while extracting is undefined behavior
+        let mut map = match unsafe { MmapMut::map_mut(&out) } {
+            Ok(map) => map,
+            Err(e) => {
+                warn!("failed to mmap {}, using buffered writes: {e}", dest.display());
+                return extract_buffered(file, out);
+            }
+        };
+
+        let (mut buf_read, mut buf_decompress) = self.alloc_read_buffers();
+        let mut raw_data = file.raw_data_reader();
+        let mut offset = 0;
+        while let Some(block) = raw_data.next_block(&mut buf_read) {
+            buf_decompress.clear();
+            raw_data.decompress(block?, &mut buf_read, &mut buf_decompress)?;
+            // same as the reader, data past the file size is ignored
+            let len = buf_decompress.len().min(map.len() - offset);
+            map[offset..][..len].copy_from_slice(&buf_decompress[..len]);
+            offset += len;
+        }
+        map.flush()?;
+        Ok(())
+    }
+}
+
+/// Write `file` into `out`, used if `out` can't be mapped
+fn extract_buffered(file: FilesystemReaderFile, out: File) -> Result<(), BackhandError> {
+    let (mut buf_read, mut buf_decompress) = file.system.alloc_read_buffers();
+    let mut reader = file.reader(&mut buf_read, &mut buf_decompress);
+    let mut out = BufWriter::new(out);
+    io::copy(&mut reader, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
@@ -1,21 +1,23 @@
 //! Reader traits
 
-use std::collections::HashMap;
 use std::io::{BufRead, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::sync::Arc;
 
 use deku::bitvec::{BitView, Msb0};
 use deku::prelude::*;
 use rustc_hash::FxHashMap;
-use tracing::{error, trace};
+use tracing::{error, trace, warn};
 
 use crate::error::BackhandError;
 use crate::export::Export;
 use crate::fragment::Fragment;
 use crate::id::Id;
-use crate::inode::Inode;
+use crate::inode::{Inode, InodeRef};
 use crate::kinds::Kind;
 use crate::metadata::METADATA_MAXSIZE;
 use crate::squashfs::{SuperBlock, NOT_SET};
+use crate::xattr::{self, Xattr, XattrId, XattrIdTable, XattrTable};
 use crate::{fragment, metadata};
 
 /// Private struct containing logic to read the `Squashfs` section from a file
@@ -59,6 +61,90 @@ impl<R: BufReadSeek> Seek for SquashfsReaderWithOffset<R> {
     }
 }
 
+/// Reader keeping the `capacity` bytes following the last read in memory
+///
+/// Unlike [`std::io::BufReader`], seeking doesn't discard the buffer. Seeking within the buffered
+/// range is served from memory, so alternating between the data of consecutive files and the
+/// metadata tables doesn't hit the underlying reader for every block.
+pub(crate) struct ReadAhead<R: BufReadSeek> {
+    io: R,
+    buf: Vec<u8>,
+    /// Position of `buf[0]` in `io`
+    buf_start: u64,
+    /// Position of the next read
+    pos: u64,
+    capacity: usize,
+}
+
+impl<R: BufReadSeek> ReadAhead<R> {
+    pub fn new(mut io: R, capacity: usize) -> std::io::Result<Self> {
+        let pos = io.stream_position()?;
+        Ok(Self { io, buf: Vec::with_capacity(capacity), buf_start: pos, pos, capacity })
+    }
+
+    /// Buffered bytes starting at `pos`, empty if `pos` is not buffered
+    fn buffered(&self) -> &[u8] {
+        match self.pos.checked_sub(self.buf_start) {
+            Some(offset) if offset < self.buf.len() as u64 => &self.buf[offset as usize..],
+            _ => &[],
+        }
+    }
+
+    /// Replace the buffer with the `capacity` bytes starting at `pos`
+    fn refill(&mut self) -> std::io::Result<()> {
+        trace!("read ahead {:#x} bytes at {:#x}", self.capacity, self.pos);
+        self.io.seek(SeekFrom::Start(self.pos))?;
+        self.buf.clear();
+        (&mut self.io).take(self.capacity as u64).read_to_end(&mut self.buf)?;
+        self.buf_start = self.pos;
+        Ok(())
+    }
+}
+
+impl<R: BufReadSeek> BufRead for ReadAhead<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.buffered().is_empty() {
+            self.refill()?;
+        }
+        Ok(self.buffered())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt.min(self.buffered().len()) as u64;
+    }
+}
+
+impl<R: BufReadSeek> Read for ReadAhead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // larger than the buffer, don't bother copying
+        if self.buffered().is_empty() && buf.len() >= self.capacity {
+            self.io.seek(SeekFrom::Start(self.pos))?;
+            let len = self.io.read(buf)?;
+            self.pos += len as u64;
+            return Ok(len);
+        }
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufReadSeek> Seek for ReadAhead<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Start(start) => start,
+            SeekFrom::Current(offset) => self
+                .pos
+                .checked_add_signed(offset)
+                .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::InvalidInput))?,
+            SeekFrom::End(_) => self.io.seek(pos)?,
+        };
+        Ok(self.pos)
+    }
+}
+
 /// Similar to to Seek, but only require the `rewind` function
 pub trait SeekRewind {
     /// Set the IO position back at the start
@@ -87,77 +173,216 @@ impl<T: BufRead + Seek + Send> BufReadSeek for T {}
 pub trait WriteSeek: Write + Seek {}
 impl<T: Write + Seek> WriteSeek for T {}
 
+/// Inodes parsed from the inode table, including their location in the table
+#[derive(Debug, Default)]
+pub struct InodeTable {
+    pub(crate) inodes: FxHashMap<u32, Inode>,
+    /// Range of each inode in the uncompressed inode table
+    pub(crate) ranges: FxHashMap<u32, Range<usize>>,
+    /// Location of each inode, as referenced by directory entries and the export table
+    pub(crate) refs: FxHashMap<u32, InodeRef>,
+    /// Inode number at each location, the reverse of `refs`
+    pub(crate) by_ref: FxHashMap<InodeRef, u32>,
+    /// For each metadata block: offset from the start of the inode table, and the offset of its
+    /// bytes in the uncompressed inode table
+    pub(crate) metadata_offsets: Vec<(u64, usize)>,
+}
+
+impl InodeTable {
+    /// Convert `uncompressed_pos` in the uncompressed inode table into an [`InodeRef`]
+    fn inode_ref(&self, uncompressed_pos: usize) -> InodeRef {
+        let index = self
+            .metadata_offsets
+            .partition_point(|(_, block_start)| *block_start <= uncompressed_pos)
+            .saturating_sub(1);
+        let (block_offset, block_start) = self.metadata_offsets[index];
+        InodeRef::new(block_offset, (uncompressed_pos - block_start) as u16)
+    }
+}
+
+/// State of [`SquashFsReader::inodes`], parsing the uncompressed metadata blocks in order
+#[derive(Default)]
+struct InodeTableParser {
+    table: InodeTable,
+    /// Bytes not yet parsed, the start of an inode continued in the next block
+    leftover: Vec<u8>,
+    /// Uncompressed bytes already removed from the start of `leftover`
+    consumed: usize,
+    /// Uncompressed bytes of all metadata blocks pushed
+    uncompressed_len: usize,
+    /// Error of the last inode not fully contained in the blocks pushed so far
+    incomplete: Option<DekuError>,
+}
+
+impl InodeTableParser {
+    /// Parse as many inodes as possible after adding `bytes`, the metadata block at
+    /// `block_offset` from the start of the inode table
+    fn push_block(
+        &mut self,
+        block_offset: u64,
+        mut bytes: Vec<u8>,
+        superblock: &SuperBlock,
+        kind: &Kind,
+    ) -> Result<(), BackhandError> {
+        let table = &mut self.table;
+        table.metadata_offsets.push((block_offset, self.uncompressed_len));
+        self.uncompressed_len += bytes.len();
+
+        // The directory inodes store the total, uncompressed size of the entire listing,
+        // including headers. Using this size, a SquashFS reader can determine if another header
+        // with further entries should be following once it reaches the end of a run.
+
+        // parse as many inodes as you can
+        let ret_bytes = &mut self.leftover;
+        ret_bytes.append(&mut bytes);
+        let consumed = self.consumed;
+
+        let mut input_bits = ret_bytes.view_bits::<deku::bitvec::Msb0>();
+        while !input_bits.is_empty() {
+            let inode_start = consumed + ret_bytes.len() - (input_bits.len() / 8);
+            match Inode::read(
+                input_bits,
+                (
+                    superblock.bytes_used,
+                    superblock.block_size,
+                    superblock.block_log,
+                    kind.inner.type_endian,
+                ),
+            ) {
+                Ok((rest, inode)) => {
+                    // Push the new Inode to the return, with the range this was read from
+                    let inode_end = consumed + ret_bytes.len() - (rest.len() / 8);
+                    let inode_ref = table.inode_ref(inode_start);
+                    table.refs.insert(inode.header.inode_number, inode_ref);
+                    table.by_ref.insert(inode_ref, inode.header.inode_number);
+                    table.ranges.insert(inode.header.inode_number, inode_start..inode_end);
+                    table.inodes.insert(inode.header.inode_number, inode);
+                    input_bits = rest;
+                }
+                Err(e) => {
+                    if let DekuError::Incomplete(_) = e {
+                        // try next block, inodes can span multiple blocks!
+                        self.incomplete = Some(e);
+                        break;
+                    } else {
+                        error!("inode at 0x{inode_start:02x?}: {e}");
+                        return Err(BackhandError::CorruptInode { offset: inode_start, error: e });
+                    }
+                }
+            }
+        }
+
+        // save leftover bits to new bits to leave for the next metadata block
+        // this is safe, input_bits is always byte aligned
+        let parsed = ret_bytes.len() - (input_bits.len() / 8);
+        ret_bytes.drain(..parsed);
+        self.consumed += parsed;
+        Ok(())
+    }
+
+    /// Returns the inodes, failing if the last inode is cut off by the end of the inode table
+    fn finish(self) -> Result<InodeTable, BackhandError> {
+        match self.incomplete {
+            Some(error) if !self.leftover.is_empty() => {
+                error!("inode at 0x{:02x?} extends past the inode table", self.consumed);
+                Err(BackhandError::CorruptInode { offset: self.consumed, error })
+            }
+            _ => Ok(self.table),
+        }
+    }
+}
+
 impl<T: BufReadSeek> SquashFsReader for T {}
 
 /// Squashfs data extraction methods implemented over [`Read`] and [`Seek`]
 pub trait SquashFsReader: BufReadSeek {
-    /// Parse Inode Table into `Vec<(position_read, Inode)>`
+    /// Parse Inode Table into [`InodeTable`]
+    ///
+    /// With the `parallel` feature, all metadata blocks are read first and decompressed on
+    /// multiple threads. The inodes are always parsed in order, as their sizes vary.
+    #[cfg(feature = "parallel")]
     fn inodes(
         &mut self,
         superblock: &SuperBlock,
         kind: &Kind,
-    ) -> Result<FxHashMap<u32, Inode>, BackhandError> {
-        self.seek(SeekFrom::Start(superblock.inode_table))?;
-
-        // The directory inodes store the total, uncompressed size of the entire listing, including headers.
-        // Using this size, a SquashFS reader can determine if another header with further entries
-        // should be following once it reaches the end of a run.
+    ) -> Result<InodeTable, BackhandError> {
+        self.inodes_parallel(superblock, kind)
+    }
 
-        let mut ret_bytes = Vec::with_capacity(METADATA_MAXSIZE);
+    /// Parse Inode Table into [`InodeTable`]
+    ///
+    /// With the `parallel` feature, all metadata blocks are read first and decompressed on
+    /// multiple threads. The inodes are always parsed in order, as their sizes vary.
+    #[cfg(not(feature = "parallel"))]
+    fn inodes(
+        &mut self,
+        superblock: &SuperBlock,
+        kind: &Kind,
+    ) -> Result<InodeTable, BackhandError> {
+        self.inodes_sequential(superblock, kind)
+    }
 
-        let mut metadata_offsets = vec![];
-        let mut ret_vec = HashMap::default();
+    /// Parse Inode Table into [`InodeTable`], decompressing each metadata block when reached
+    ///
+    /// With the `parallel` feature, only used to compare against `inodes_parallel` in tests.
+    #[cfg_attr(feature = "parallel", allow(dead_code))]
+    fn inodes_sequential(
+        &mut self,
+        superblock: &SuperBlock,
+        kind: &Kind,
+    ) -> Result<InodeTable, BackhandError> {
+        self.seek(SeekFrom::Start(superblock.inode_table))?;
+        let mut parser = InodeTableParser::default();
         let start = self.stream_position()?;
-
         while self.stream_position()? < superblock.dir_table {
             trace!("offset: {:02x?}", self.stream_position());
-            metadata_offsets.push(self.stream_position()? - start);
-            // parse into metadata
-            let mut bytes = metadata::read_block(self, superblock, kind)?;
+            let block_offset = self.stream_position()? - start;
+            let bytes = metadata::read_block(self, superblock, kind)?;
+            parser.push_block(block_offset, bytes, superblock, kind)?;
+        }
+        parser.finish()
+    }
 
-            // parse as many inodes as you can
-            ret_bytes.append(&mut bytes);
-
-            let mut input_bits = ret_bytes.view_bits::<deku::bitvec::Msb0>();
-            while !input_bits.is_empty() {
-                match Inode::read(
-                    input_bits,
-                    (
-                        superblock.bytes_used,
-                        superblock.block_size,
-                        superblock.block_log,
-                        kind.inner.type_endian,
-                    ),
-                ) {
-                    Ok((rest, inode)) => {
-                        // Push the new Inode to the return, with the position this was read from
-                        ret_vec.insert(inode.header.inode_number, inode);
-                        input_bits = rest;
-                    }
-                    Err(e) => {
-                        if let DekuError::Incomplete(_) = e {
-                            // try next block, inodes can span multiple blocks!
-                            break;
-                        } else {
-                            error!("{e}");
-                            return Err(BackhandError::Deku(e));
-                        }
-                    }
-                }
-            }
+    /// Parse Inode Table into [`InodeTable`], reading all metadata blocks first, then
+    /// decompressing them across threads
+    #[cfg(feature = "parallel")]
+    fn inodes_parallel(
+        &mut self,
+        superblock: &SuperBlock,
+        kind: &Kind,
+    ) -> Result<InodeTable, BackhandError> {
+        use rayon::prelude::*;
 
-            // save leftover bits to new bits to leave for the next metadata block
-            // this is safe, input_bits is always byte aligned
-            ret_bytes.drain(..(ret_bytes.len() - (input_bits.len() / 8)));
+        self.seek(SeekFrom::Start(superblock.inode_table))?;
+        let start = self.stream_position()?;
+        let mut raw_blocks = vec![];
+        while self.stream_position()? < superblock.dir_table {
+            trace!("offset: {:02x?}", self.stream_position());
+            let block_offset = self.stream_position()? - start;
+            raw_blocks.push((block_offset, metadata::read_raw_block(self, kind)?));
         }
 
-        Ok(ret_vec)
+        let blocks: Vec<(u64, Vec<u8>)> = raw_blocks
+            .into_par_iter()
+            .map(|(block_offset, (metadata_len, bytes))| {
+                let bytes =
+                    metadata::decompress_block(metadata_len, bytes, kind, superblock.compressor)?;
+                Ok((block_offset, bytes))
+            })
+            .collect::<Result<_, BackhandError>>()?;
+
+        let mut parser = InodeTableParser::default();
+        for (block_offset, bytes) in blocks {
+            parser.push_block(block_offset, bytes, superblock, kind)?;
+        }
+        parser.finish()
     }
 
     /// Extract the root `Inode` as a `BasicDirectory`
     fn root_inode(&mut self, superblock: &SuperBlock, kind: &Kind) -> Result<Inode, BackhandError> {
-        let root_inode_start = (superblock.root_inode >> 16) as usize;
-        let root_inode_offset = (superblock.root_inode & 0xffff) as usize;
+        let root_inode_ref = superblock.root_inode_ref();
+        let root_inode_start = root_inode_ref.block_start() as usize;
+        let root_inode_offset = root_inode_ref.offset() as usize;
         trace!("root_inode_start:  0x{root_inode_start:02x?}");
         trace!("root_inode_offset: 0x{root_inode_offset:02x?}");
         if (root_inode_start as u64) > superblock.bytes_used {
@@ -186,7 +411,7 @@ pub trait SquashFsReader: BufReadSeek {
                 kind.inner.type_endian,
             ),
         ) {
-            return Ok(inode);
+            return check_root_dir(inode);
         }
 
         // if that doesn't work, we need another block
@@ -208,27 +433,61 @@ pub trait SquashFsReader: BufReadSeek {
                 kind.inner.type_endian,
             ),
         ) {
-            Ok((_, inode)) => Ok(inode),
+            Ok((_, inode)) => check_root_dir(inode),
             Err(e) => Err(e.into()),
         }
     }
 
     /// Parse required number of `Metadata`s uncompressed blocks required for `Dir`s
+    ///
+    /// `dir_extents` contains, for each directory, the start of its first metadata block and the
+    /// amount of uncompressed bytes needed from that block start. Reading stops once all are
+    /// covered, or at `end_ptr`.
     fn dir_blocks(
         &mut self,
         superblock: &SuperBlock,
         end_ptr: u64,
+        dir_extents: &[(u64, usize)],
         kind: &Kind,
     ) -> Result<Vec<(u64, Vec<u8>)>, BackhandError> {
         let seek = superblock.dir_table;
         self.seek(SeekFrom::Start(seek))?;
-        let mut all_bytes = vec![];
-        while self.stream_position()? != end_ptr {
+        let Some(last_block_index) = dir_extents.iter().map(|(block_index, _)| *block_index).max()
+        else {
+            // only empty directories
+            return Ok(vec![]);
+        };
+        let mut all_bytes: Vec<(u64, Vec<u8>)> = vec![];
+        // uncompressed bytes read, before each block
+        let mut uncompressed_before = vec![];
+        let mut uncompressed_len = 0;
+        // every block read advances the position by at least its header, so this always ends
+        while self.stream_position()? < end_ptr {
             let metadata_start = self.stream_position()?;
             let bytes = metadata::read_block(self, superblock, kind)?;
+            uncompressed_before.push(uncompressed_len);
+            uncompressed_len += bytes.len();
             all_bytes.push((metadata_start - seek, bytes));
+
+            // only possible to be done once the block of every directory was read
+            if metadata_start - seek < last_block_index {
+                continue;
+            }
+            let covered = dir_extents.iter().all(|(block_index, needed)| {
+                match all_bytes.binary_search_by_key(block_index, |(start, _)| *start) {
+                    Ok(i) => uncompressed_len - uncompressed_before[i] >= *needed,
+                    // not the start of a block, the dir table is corrupted
+                    Err(_) => false,
+                }
+            });
+            if covered {
+                trace!("read all directories, stopping at 0x{:02x?}", self.stream_position()?);
+                return Ok(all_bytes);
+            }
         }
 
+        // the directories not fully read are reported while creating the tree
+        warn!("directory table ended at 0x{end_ptr:02x?} before all directories were read");
         Ok(all_bytes)
     }
 
@@ -237,18 +496,22 @@ pub trait SquashFsReader: BufReadSeek {
         &mut self,
         superblock: &SuperBlock,
         kind: &Kind,
-    ) -> Result<Option<(u64, Vec<Fragment>)>, BackhandError> {
-        if superblock.frag_count == 0 || superblock.frag_table == NOT_SET {
+    ) -> Result<Option<(u64, Vec<Fragment>, usize)>, BackhandError> {
+        if superblock.frag_count == 0 {
             return Ok(None);
         }
-        let (ptr, table) = self.lookup_table::<Fragment>(
+        if superblock.frag_table == NOT_SET {
+            error!("frag_count is {}, but frag_table is not set", superblock.frag_count);
+            return Err(BackhandError::MissingFragmentTable);
+        }
+        let table = self.lookup_table::<Fragment>(
             superblock,
             superblock.frag_table,
             u64::from(superblock.frag_count) * fragment::SIZE as u64,
             kind,
         )?;
 
-        Ok(Some((ptr, table)))
+        Ok(Some(table))
     }
 
     /// Parse Export Table
@@ -256,12 +519,12 @@ pub trait SquashFsReader: BufReadSeek {
         &mut self,
         superblock: &SuperBlock,
         kind: &Kind,
-    ) -> Result<Option<(u64, Vec<Export>)>, BackhandError> {
+    ) -> Result<Option<(u64, Vec<Export>, usize)>, BackhandError> {
         if superblock.nfs_export_table_exists() && superblock.export_table != NOT_SET {
             let ptr = superblock.export_table;
             let count = (superblock.inode_count as f32 / 1024_f32).ceil() as u64;
-            let (ptr, table) = self.lookup_table::<Export>(superblock, ptr, count, kind)?;
-            Ok(Some((ptr, table)))
+            let table = self.lookup_table::<Export>(superblock, ptr, count, kind)?;
+            Ok(Some(table))
         } else {
             Ok(None)
         }
@@ -272,21 +535,75 @@ pub trait SquashFsReader: BufReadSeek {
         &mut self,
         superblock: &SuperBlock,
         kind: &Kind,
-    ) -> Result<(u64, Vec<Id>), BackhandError> {
+    ) -> Result<(u64, Vec<Id>, usize), BackhandError> {
         let ptr = superblock.id_table;
         let count = superblock.id_count as u64;
-        let (ptr, table) = self.lookup_table::<Id>(superblock, ptr, count, kind)?;
-        Ok((ptr, table))
+        if count == 0 {
+            // nothing to read, and the dir table still ends before the (empty) table
+            warn!("id_count is zero, uid and gid are used as is");
+            return Ok((ptr, vec![], 0));
+        }
+        self.lookup_table::<Id>(superblock, ptr, count, kind)
     }
 
-    /// Parse Lookup Table
+    /// Parse Xattr Table, returning the attributes of each xattr index
+    fn xattrs(
+        &mut self,
+        superblock: &SuperBlock,
+        kind: &Kind,
+    ) -> Result<Option<Vec<Arc<[Xattr]>>>, BackhandError> {
+        let Some(seek) = superblock.xattr_table_in_use() else {
+            return Ok(None);
+        };
+        self.seek(SeekFrom::Start(seek))?;
+        let mut buf = [0u8; xattr::ID_TABLE_SIZE];
+        self.read_exact(&mut buf)?;
+        let bv = buf.view_bits::<Msb0>();
+        let (_, id_table) = XattrIdTable::read(bv, kind.inner.type_endian)?;
+        trace!("{:02x?}", id_table);
+        if id_table.xattr_ids == 0 {
+            return Ok(Some(vec![]));
+        }
+
+        let (ids_ptr, ids, _) = self.lookup_table::<XattrId>(
+            superblock,
+            seek + xattr::ID_TABLE_SIZE as u64,
+            u64::from(id_table.xattr_ids) * xattr::ID_SIZE as u64,
+            kind,
+        )?;
+
+        // the keys and values are stored before the ids
+        let start = id_table.xattr_table_start;
+        if start < superblock.inode_table || start > ids_ptr {
+            error!("xattr_table_start 0x{start:02x?} outside of the metadata region");
+            return Err(BackhandError::CorruptedTablePointer);
+        }
+        self.seek(SeekFrom::Start(start))?;
+        let mut bytes = vec![];
+        let mut block_starts = vec![];
+        while self.stream_position()? < ids_ptr {
+            block_starts.push((self.stream_position()? - start, bytes.len()));
+            bytes.append(&mut metadata::read_block(self, superblock, kind)?);
+        }
+
+        let table = XattrTable { bytes, block_starts, endian: kind.inner.type_endian };
+        let xattrs = ids
+            .iter()
+            .take(id_table.xattr_ids as usize)
+            .map(|id| table.xattrs(id).map(Arc::from))
+            .collect::<Result<_, _>>()?;
+        Ok(Some(xattrs))
+    }
+
+    /// Parse Lookup Table, returning the start of its metadata, the table, and the amount of
+    /// metadata blocks
     fn lookup_table<T: for<'a> DekuRead<'a, deku::ctx::Endian>>(
         &mut self,
         superblock: &SuperBlock,
         seek: u64,
         size: u64,
         kind: &Kind,
-    ) -> Result<(u64, Vec<T>), BackhandError> {
+    ) -> Result<(u64, Vec<T>, usize), BackhandError> {
         // find the pointer at the initial offset
         trace!("seek: {:02x?}", seek);
         self.seek(SeekFrom::Start(seek))?;
@@ -297,12 +614,27 @@ pub trait SquashFsReader: BufReadSeek {
         let bv = buf.view_bits::<deku::bitvec::Msb0>();
         let (_, ptr) = u64::read(bv, kind.inner.type_endian)?;
 
+        // the metadata blocks of the table are located after the inode table, and before the
+        // pointers to them
+        if ptr < superblock.inode_table || ptr >= seek || ptr >= superblock.bytes_used {
+            error!("table pointer 0x{ptr:02x?} outside of the metadata region");
+            return Err(BackhandError::CorruptedTablePointer);
+        }
+
         let block_count = (size as f32 / METADATA_MAXSIZE as f32).ceil() as u64;
+        // every metadata block is at least its header, and must fit before the pointers to them
+        if block_count.saturating_mul(2) > seek - ptr {
+            error!(
+                "table of {block_count} metadata blocks doesn't fit in 0x{:02x?} bytes",
+                seek - ptr
+            );
+            return Err(BackhandError::TableTooLarge);
+        }
 
         trace!("ptr: {:02x?}", ptr);
         let table = self.metadata_with_count::<T>(superblock, ptr, block_count, kind)?;
 
-        Ok((ptr, table))
+        Ok((ptr, table, block_count as usize))
     }
 
     /// Parse count of `Metadata` block at offset into `T`
@@ -314,6 +646,12 @@ pub trait SquashFsReader: BufReadSeek {
         kind: &Kind,
     ) -> Result<Vec<T>, BackhandError> {
         trace!("seek: {:02x?}", seek);
+        // every metadata block is at least its header, don't attempt reading past the image
+        let remaining = superblock.bytes_used.saturating_sub(seek);
+        if count.saturating_mul(2) > remaining {
+            error!("{count} metadata blocks don't fit in the remaining 0x{remaining:02x?} bytes");
+            return Err(BackhandError::TableTooLarge);
+        }
         self.seek(SeekFrom::Start(seek))?;
 
         let mut all_bytes = vec![];
@@ -333,3 +671,72 @@ pub trait SquashFsReader: BufReadSeek {
         Ok(ret_vec)
     }
 }
+
+/// Only allow a directory as the root inode
+fn check_root_dir(inode: Inode) -> Result<Inode, BackhandError> {
+    if inode.as_dir().is_err() {
+        error!("root inode is not a directory");
+        return Err(BackhandError::RootNotDirectory);
+    }
+    Ok(inode)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::compressor::Compressor;
+    use crate::kinds::LE_V4_0;
+
+    #[test]
+    fn test_lookup_table_pointer_out_of_range() {
+        let kind = Kind::from_const(LE_V4_0).unwrap();
+        let mut superblock = SuperBlock::new(Compressor::None, Kind::from_kind(&kind));
+        superblock.inode_table = 0x60;
+        superblock.bytes_used = 0x100;
+
+        let mut bytes = vec![0u8; 0x100];
+        let table = 0xf0;
+
+        // pointer past the end of the image
+        bytes[table..][..8].copy_from_slice(&0x1000_u64.to_le_bytes());
+        let mut reader = Cursor::new(bytes.clone());
+        let ret = reader.lookup_table::<Id>(&superblock, table as u64, Id::SIZE as u64, &kind);
+        assert!(matches!(ret, Err(BackhandError::CorruptedTablePointer)));
+
+        // pointer into the data region
+        bytes[table..][..8].copy_from_slice(&0x10_u64.to_le_bytes());
+        let mut reader = Cursor::new(bytes);
+        let ret = reader.lookup_table::<Id>(&superblock, table as u64, Id::SIZE as u64, &kind);
+        assert!(matches!(ret, Err(BackhandError::CorruptedTablePointer)));
+    }
+
+    #[test]
+    #[cfg(all(feature = "parallel", feature = "xz"))]
+    fn test_inodes_parallel_matches_sequential() {
+        use crate::{FilesystemWriter, NodeHeader, Squashfs};
+
+        let mut fs = FilesystemWriter::default();
+        for i in 0..1000 {
+            let data = Cursor::new(format!("{i}").into_bytes());
+            fs.push_file(data, format!("file{i}"), NodeHeader::default()).unwrap();
+        }
+        let mut image = Cursor::new(vec![]);
+        fs.write(&mut image).unwrap();
+        let image = image.into_inner();
+        let squashfs = Squashfs::from_reader(Cursor::new(image.clone())).unwrap();
+        let (superblock, kind) = (&squashfs.superblock, &squashfs.kind);
+
+        let parallel = Cursor::new(image.clone()).inodes_parallel(superblock, kind).unwrap();
+        let sequential = Cursor::new(image).inodes_sequential(superblock, kind).unwrap();
+        // inodes split across metadata blocks
+        assert!(sequential.metadata_offsets.len() > 2);
+        assert_eq!(parallel.inodes.len(), 1001);
+        assert_eq!(parallel.inodes, sequential.inodes);
+        assert_eq!(parallel.ranges, sequential.ranges);
+        assert_eq!(parallel.refs, sequential.refs);
+        assert_eq!(parallel.by_ref, sequential.by_ref);
+        assert_eq!(parallel.metadata_offsets, sequential.metadata_offsets);
+    }
+}
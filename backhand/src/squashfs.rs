@@ -1,28 +1,37 @@
 //! Read from on-disk image
 
+use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::ffi::OsString;
-use std::io::{Seek, SeekFrom};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
 use std::os::unix::prelude::OsStringExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use deku::bitvec::{BitVec, BitView, Msb0};
 use deku::prelude::*;
-use rustc_hash::FxHashMap;
-use tracing::{error, info, trace};
+use rustc_hash::{FxHashMap, FxHashSet};
+use tracing::{error, info, trace, warn};
 
-use crate::compressor::{CompressionOptions, Compressor};
-use crate::dir::Dir;
+use crate::compressor::{
+    compression_options_size, CompressionOptions, Compressor, Gzip, Lz4, Lzo, Xz, Zstd,
+};
+use crate::dir::{Dir, DirIndexEntry, ReadDirEntry};
 use crate::error::BackhandError;
 use crate::filesystem::node::{InnerNode, Nodes};
 use crate::fragment::Fragment;
-use crate::inode::{Inode, InodeId, InodeInner};
+use crate::inode::{
+    BasicDirectory, DirectoryInode, Inode, InodeHeader, InodeId, InodeInner, InodeRef,
+};
 use crate::kinds::{Kind, LE_V4_0};
 use crate::reader::{BufReadSeek, SquashFsReader, SquashfsReaderWithOffset};
 use crate::{
     metadata, Export, FilesystemReader, Id, Node, NodeHeader, SquashfsBlockDevice,
-    SquashfsCharacterDevice, SquashfsDir, SquashfsFileReader, SquashfsSymlink,
+    SquashfsCharacterDevice, SquashfsDir, SquashfsFileReader, SquashfsSymlink, Xattr,
 };
 
 /// 128KiB
@@ -88,15 +97,47 @@ pub struct SuperBlock {
     pub export_table: u64,
 }
 
+/// Default maximum depth of nested directories, see [`Squashfs::set_max_dir_depth`]
+pub const DEFAULT_MAX_DIR_DEPTH: usize = 1024;
+
 pub const NOT_SET: u64 = 0xffff_ffff_ffff_ffff;
 
 impl SuperBlock {
+    /// `xattr_table`, or `None` if the image has no xattr table and it is `0xffff_ffff_ffff_ffff`
+    pub fn xattr_table_offset(&self) -> Option<u64> {
+        (self.xattr_table != NOT_SET).then_some(self.xattr_table)
+    }
+
+    /// Same as [`Self::xattr_table_offset`], but `None` if the `NoXattrsInArchive` flag is set
+    ///
+    /// The flag wins over a contradicting `xattr_table`, such an image is read without xattrs.
+    pub fn xattr_table_in_use(&self) -> Option<u64> {
+        if self.no_xattrs_in_archive() {
+            return None;
+        }
+        self.xattr_table_offset()
+    }
+
+    /// `frag_table`, or `None` if the image has no fragment table and it is `0xffff_ffff_ffff_ffff`
+    pub fn frag_table_offset(&self) -> Option<u64> {
+        (self.frag_table != NOT_SET).then_some(self.frag_table)
+    }
+
+    /// `export_table`, or `None` if the image has no export table and it is `0xffff_ffff_ffff_ffff`
+    pub fn export_table_offset(&self) -> Option<u64> {
+        (self.export_table != NOT_SET).then_some(self.export_table)
+    }
+
     /// flag value
     pub fn inodes_uncompressed(&self) -> bool {
         self.flags & Flags::InodesStoredUncompressed as u16 != 0
     }
 
     /// flag value
+    ///
+    /// Only informational when reading: whether a data block is compressed is decided by the
+    /// uncompressed bit of its own size, see [`crate::DataSize::uncompressed`]. If the two
+    /// disagree, the bit of the block wins.
     pub fn data_block_stored_uncompressed(&self) -> bool {
         self.flags & Flags::DataBlockStoredUncompressed as u16 != 0
     }
@@ -143,6 +184,53 @@ impl SuperBlock {
 }
 
 impl SuperBlock {
+    /// Size of the serialized superblock in bytes
+    pub const SIZE: usize = 96;
+
+    /// Serialize into the on-disk representation, using the magic, version, and endian of `kind`
+    pub fn to_bytes(&self, kind: &Kind) -> Result<Vec<u8>, BackhandError> {
+        let mut bv = BitVec::new();
+        self.write(
+            &mut bv,
+            (
+                kind.inner.magic,
+                kind.inner.version_major,
+                kind.inner.version_minor,
+                kind.inner.type_endian,
+            ),
+        )?;
+        Ok(bv.into_vec())
+    }
+
+    /// Returns `true` if `1 << block_log == block_size`
+    ///
+    /// Opening an image requires this, but tools inspecting a superblock can use this to warn
+    /// instead.
+    pub fn block_log_consistent(&self) -> bool {
+        1u32.checked_shl(u32::from(self.block_log)) == Some(self.block_size)
+    }
+
+    /// `root_inode` decoded into the location of the root inode in the inode table
+    pub fn root_inode_ref(&self) -> InodeRef {
+        InodeRef(self.root_inode)
+    }
+
+    /// `mod_time` interpreted as a [`SystemTime`]
+    ///
+    /// `mod_time` is unsigned seconds since the unix epoch, so this is never before 1970, and
+    /// only rolls over in 2106. See [`Self::modified_time_checked`] for rejecting unset values.
+    pub fn modified_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(u64::from(self.mod_time))
+    }
+
+    /// `mod_time` interpreted as a [`SystemTime`]
+    ///
+    /// Returns `None` if the value is suspicious: either `0` or further in the future than the
+    /// current system time. Use `mod_time` directly for the raw value.
+    pub fn modified_time_checked(&self) -> Option<SystemTime> {
+        checked_mod_time(self.mod_time)
+    }
+
     pub fn new(compressor: Compressor, kind: Kind) -> Self {
         Self {
             magic: kind.inner.magic,
@@ -168,6 +256,18 @@ impl SuperBlock {
     }
 }
 
+/// Convert a squashfs timestamp into a [`SystemTime`], rejecting `0` and future timestamps
+pub(crate) fn checked_mod_time(mod_time: u32) -> Option<SystemTime> {
+    if mod_time == 0 {
+        return None;
+    }
+    let time = UNIX_EPOCH + Duration::from_secs(u64::from(mod_time));
+    if time > SystemTime::now() {
+        return None;
+    }
+    Some(time)
+}
+
 #[rustfmt::skip]
 #[allow(dead_code)]
 #[derive(Debug, Copy, Clone)]
@@ -190,6 +290,217 @@ pub(crate) struct Cache {
     /// The first time a fragment bytes is read, those bytes are added to this map with the key
     /// representing the start position
     pub(crate) fragment_cache: FxHashMap<u64, Vec<u8>>,
+    /// Keys of `fragment_cache` from the least to the most recently used, only kept if bounded
+    fragment_order: VecDeque<u64>,
+    /// Maximum amount of entries in `fragment_cache`, unbounded if `None`
+    pub(crate) fragment_cache_capacity: Option<usize>,
+}
+
+impl Cache {
+    pub(crate) fn new(fragment_cache_capacity: Option<usize>) -> Self {
+        Self {
+            fragment_cache: FxHashMap::default(),
+            fragment_order: VecDeque::new(),
+            fragment_cache_capacity,
+        }
+    }
+
+    /// Uncompressed fragment block at `start`, marking it as the most recently used
+    pub(crate) fn fragment(&mut self, start: u64) -> Option<&[u8]> {
+        let bytes = self.fragment_cache.get(&start)?;
+        if self.fragment_cache_capacity.is_some() {
+            self.fragment_order.retain(|key| *key != start);
+            self.fragment_order.push_back(start);
+        }
+        Some(bytes)
+    }
+
+    /// Add the uncompressed fragment block at `start`, evicting the least recently used if full
+    pub(crate) fn insert_fragment(&mut self, start: u64, bytes: Vec<u8>) {
+        if let Some(capacity) = self.fragment_cache_capacity {
+            if capacity == 0 {
+                return;
+            }
+            if self.fragment_cache.contains_key(&start) {
+                self.fragment_order.retain(|key| *key != start);
+            } else if self.fragment_cache.len() >= capacity {
+                if let Some(evicted) = self.fragment_order.pop_front() {
+                    self.fragment_cache.remove(&evicted);
+                }
+            }
+            self.fragment_order.push_back(start);
+        }
+        self.fragment_cache.insert(start, bytes);
+    }
+}
+
+/// Options for opening a [`Squashfs`], in one place instead of the `from_reader*` constructors
+///
+/// # Example
+/// ```rust,no_run
+/// # use std::fs::File;
+/// # use std::io::BufReader;
+/// # use backhand::SquashfsReaderBuilder;
+/// let file = BufReader::new(File::open("firmware.bin").unwrap());
+/// let squashfs = SquashfsReaderBuilder::new()
+///     .offset(0x40000)
+///     .max_dir_depth(64)
+///     .cache_capacity(16)
+///     .strict(true)
+///     .build(file)
+///     .unwrap();
+/// ```
+pub struct SquashfsReaderBuilder {
+    offset: u64,
+    kind: Kind,
+    max_dir_depth: usize,
+    cache_capacity: Option<usize>,
+    strict: bool,
+}
+
+impl Default for SquashfsReaderBuilder {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            kind: Kind { inner: Arc::new(LE_V4_0) },
+            max_dir_depth: DEFAULT_MAX_DIR_DEPTH,
+            cache_capacity: None,
+            strict: false,
+        }
+    }
+}
+
+impl SquashfsReaderBuilder {
+    /// Same as [`Squashfs::from_reader`] if nothing else is set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Offset of the image in the reader, see [`Squashfs::from_reader_with_offset`]
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Version and compressor of the image, such as a [`Kind`] with a custom
+    /// [`CompressionAction`](crate::compression::CompressionAction). Defaults to [`LE_V4_0`].
+    pub fn kind(mut self, kind: Kind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// See [`Squashfs::set_max_dir_depth`]
+    pub fn max_dir_depth(mut self, max_dir_depth: usize) -> Self {
+        self.max_dir_depth = max_dir_depth;
+        self
+    }
+
+    /// See [`Squashfs::set_cache_capacity`]
+    pub fn cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache_capacity = Some(capacity);
+        self
+    }
+
+    /// Return [`BackhandError::Strict`] for every [`ReadWarning`], instead of recovering from it
+    ///
+    /// The problems found while opening the image are returned by [`Self::build`], the others
+    /// once found, such as by [`Squashfs::into_filesystem_reader`]. Defaults to `false`.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Parse the image from `reader`, see [`Squashfs::from_reader_with_offset_and_kind`]
+    pub fn build<'b>(self, reader: impl BufReadSeek + 'b) -> Result<Squashfs<'b>, BackhandError> {
+        let mut squashfs =
+            Squashfs::from_reader_with_offset_and_kind(reader, self.offset, self.kind)?;
+        if self.strict {
+            if let Some(warning) = squashfs.warnings.first() {
+                error!("rejected by strict mode: {warning:?}");
+                return Err(BackhandError::Strict(*warning));
+            }
+        }
+        squashfs.set_max_dir_depth(self.max_dir_depth);
+        squashfs.cache_capacity = self.cache_capacity;
+        squashfs.strict = self.strict;
+        Ok(squashfs)
+    }
+}
+
+/// Amount of metadata blocks of each table, returned by [`Squashfs::table_block_counts`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TableBlockCounts {
+    pub inode: usize,
+    /// Only the blocks used by the directories, see [`Squashfs::dir_blocks`]
+    pub dir: usize,
+    /// `0` if there is no fragment table
+    pub fragment: usize,
+    /// `0` if there is no export table
+    pub export: usize,
+    pub id: usize,
+}
+
+/// Diagnostics gathered by [`Squashfs::into_filesystem_reader_diagnostic`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpenDiagnostics {
+    /// `inode_count` as stored in the [`SuperBlock`]
+    pub superblock_inode_count: u32,
+    /// Amount of inodes parsed from the inode table
+    pub parsed_inode_count: usize,
+    /// Inode numbers that were parsed, but never referenced from the directory tree, sorted
+    pub unreachable_inodes: Vec<u32>,
+}
+
+/// Recoverable problem found while reading an image
+///
+/// The problems of the superblock and tables are found while opening the image, see
+/// [`Squashfs::warnings`]. The others are found later, and only logged. All of them are returned
+/// as [`BackhandError::Strict`] instead, if opened with [`SquashfsReaderBuilder::strict`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReadWarning {
+    /// The compressor is [`Compressor::None`], but the superblock flags compression options as
+    /// present. The flag is ignored.
+    CompressionOptionsWithoutCompressor,
+    /// `size` bytes of compression options don't match `compressor`, a sign of a mislabeled
+    /// compressor. The options are still parsed for `compressor`.
+    CompressionOptionsSize { compressor: Compressor, size: usize },
+    /// The `xattr_table` is set, but the superblock flags the image as having no extended
+    /// attributes. The table is ignored.
+    XattrTableWithNoXattrsFlag,
+    /// `id_count` is zero, the uid and gid indices of the inodes are used as ids
+    EmptyIdTable,
+    /// The uid or gid `index` of inode `inode_number` is outside of the id table, and used as id.
+    /// Found while creating the [`FilesystemReader`].
+    IdIndexOutOfRange { inode_number: u32, index: u16 },
+    /// Inode `inode_number` has an xattr index, but the image has no xattr table. The index is
+    /// ignored. Found while creating the [`FilesystemReader`].
+    XattrIndexWithoutTable { inode_number: u32 },
+    /// Directory inode `inode_number` is referenced again by another directory entry, which is
+    /// skipped. Found while creating the [`FilesystemReader`].
+    DirectoryReferencedAgain { inode_number: u32 },
+    /// The export table entry of `inode_number` references an inode of another number, which
+    /// is used. Found by [`Squashfs::export_lookup`].
+    ExportEntryMismatch { inode_number: u32 },
+}
+
+/// All owned components of a [`Squashfs`], returned by [`Squashfs::into_parts`]
+pub struct SquashfsParts<'b> {
+    pub kind: Kind,
+    pub superblock: SuperBlock,
+    pub compression_options: Option<CompressionOptions>,
+    pub inodes: FxHashMap<u32, Inode>,
+    pub inode_refs: FxHashMap<u32, InodeRef>,
+    pub root_inode: Inode,
+    pub dir_blocks: Vec<(u64, Vec<u8>)>,
+    pub fragments: Option<Vec<Fragment>>,
+    pub export: Option<Vec<Export>>,
+    pub id: Vec<Id>,
+    pub xattrs: Option<Vec<Arc<[Xattr]>>>,
+    /// Reader of the image, seeking to `0` is the start of the image, not of the original file
+    pub reader: Box<dyn BufReadSeek + 'b>,
+    /// Offset of the image in the original file
+    pub offset: u64,
 }
 
 /// Squashfs Image initial read information
@@ -202,6 +513,8 @@ pub struct Squashfs<'b> {
     pub compression_options: Option<CompressionOptions>,
     // All Inodes
     pub inodes: FxHashMap<u32, Inode>,
+    /// Location of all inodes in the inode table
+    pub inode_refs: FxHashMap<u32, InodeRef>,
     /// Root Inode
     pub root_inode: Inode,
     /// Bytes containing Directory Table
@@ -212,8 +525,117 @@ pub struct Squashfs<'b> {
     pub export: Option<Vec<Export>>,
     /// Id Lookup Table
     pub id: Vec<Id>,
+    /// Extended attributes of each xattr index, `None` if the image has no xattr table
+    pub xattrs: Option<Vec<Arc<[Xattr]>>>,
     //file reader
     file: Box<dyn BufReadSeek + 'b>,
+    // maximum depth of nested directories
+    max_dir_depth: usize,
+    // maximum amount of fragment blocks cached by the `FilesystemReader`
+    cache_capacity: Option<usize>,
+    // offset of the image in the original file
+    offset: u64,
+    // range of each inode in the uncompressed inode table
+    inode_ranges: FxHashMap<u32, Range<usize>>,
+    // for each inode metadata block: (offset from inode table, offset in uncompressed inode table)
+    inode_metadata_offsets: Vec<(u64, usize)>,
+    // inode number at each location in the inode table, the reverse of `inode_refs` as read
+    inode_numbers_by_ref: FxHashMap<InodeRef, u32>,
+    // amount of metadata blocks read for each table
+    table_block_counts: TableBlockCounts,
+    // uncompressed bytes of the compression options, as stored in the image
+    compression_options_raw: Option<Vec<u8>>,
+    // recoverable problems found while reading the image
+    warnings: Vec<ReadWarning>,
+    // return the recoverable problems found after opening as errors
+    strict: bool,
+}
+
+/// Returns `true` if the bytes at `offset` of `reader` parse as a superblock of `kind`, with a
+/// valid block size
+///
+/// The position of `reader` is restored afterwards.
+fn plausible_superblock<R: Read + Seek>(reader: &mut R, offset: u64, kind: &Kind) -> bool {
+    let Ok(position) = reader.stream_position() else {
+        return false;
+    };
+    let mut bytes = [0u8; SuperBlock::SIZE];
+    let read = reader.seek(SeekFrom::Start(offset)).and_then(|_| reader.read_exact(&mut bytes));
+    if reader.seek(SeekFrom::Start(position)).is_err() || read.is_err() {
+        return false;
+    }
+
+    let ctx = (
+        kind.inner.magic,
+        kind.inner.version_major,
+        kind.inner.version_minor,
+        kind.inner.type_endian,
+    );
+    let Ok((_, superblock)) = SuperBlock::read(bytes.view_bits::<Msb0>(), ctx) else {
+        trace!("magic at {offset:#x} is not followed by a superblock");
+        return false;
+    };
+    (MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&superblock.block_size)
+        && superblock.block_log_consistent()
+}
+
+/// Scan `reader` from its start for `magic` at every multiple of `stride` bytes, returning the
+/// first offset accepted by `accept`
+///
+/// `accept` can read `reader`, but must restore its position.
+fn scan_magic<R, F>(
+    reader: &mut R,
+    magic: &[u8],
+    stride: u64,
+    mut accept: F,
+) -> Result<Option<u64>, std::io::Error>
+where
+    R: Read + Seek,
+    F: FnMut(&mut R, u64) -> bool,
+{
+    reader.rewind()?;
+
+    let mut buf = vec![0; 0x10000];
+    // bytes in `buf` kept from the previous read, so a magic split across reads is found
+    let mut carry = 0;
+    // offset in `reader` of `buf[0]`
+    let mut buf_offset = 0;
+    loop {
+        let n = reader.read(&mut buf[carry..])?;
+        if n == 0 {
+            return Ok(None);
+        }
+        let filled = carry + n;
+        for (pos, window) in buf[..filled].windows(magic.len()).enumerate() {
+            let candidate = buf_offset + pos as u64;
+            if candidate % stride == 0 && window == magic && accept(reader, candidate) {
+                return Ok(Some(candidate));
+            }
+        }
+        carry = filled.min(magic.len() - 1);
+        buf.copy_within(filled - carry..filled, 0);
+        buf_offset += (filled - carry) as u64;
+    }
+}
+
+/// Empty root directory, for images without any inodes
+fn empty_root_inode(superblock: &SuperBlock) -> Inode {
+    let header = InodeHeader {
+        permissions: 0o755,
+        uid: 0,
+        gid: 0,
+        mtime: superblock.mod_time,
+        inode_number: 1,
+    };
+    let dir = BasicDirectory {
+        block_index: 0,
+        link_count: 2,
+        // the size of an empty directory includes the implicit `.` and `..`
+        file_size: 3,
+        block_offset: 0,
+        parent_inode: 0,
+    };
+    Inode::new(InodeId::BasicDirectory, header, InodeInner::BasicDirectory(dir))
 }
 
 impl<'b> Squashfs<'b> {
@@ -225,8 +647,20 @@ impl<'b> Squashfs<'b> {
         reader: &mut Box<dyn BufReadSeek + 'b>,
         kind: &Kind,
     ) -> Result<(SuperBlock, Option<CompressionOptions>), BackhandError> {
+        let (superblock, compression_options, _) =
+            Self::superblock_and_raw_compression_options(reader, kind, &mut vec![])?;
+        Ok((superblock, compression_options))
+    }
+
+    /// Same as [`Self::superblock_and_compression_options`], but also returning the
+    /// uncompressed bytes of the compression options, and pushing the problems found to `warnings`
+    fn superblock_and_raw_compression_options(
+        reader: &mut Box<dyn BufReadSeek + 'b>,
+        kind: &Kind,
+        warnings: &mut Vec<ReadWarning>,
+    ) -> Result<(SuperBlock, Option<CompressionOptions>, Option<Vec<u8>>), BackhandError> {
         // Size of metadata + optional compression options metadata block
-        let mut superblock = [0u8; 96];
+        let mut superblock = [0u8; SuperBlock::SIZE];
         reader.read_exact(&mut superblock)?;
 
         // Parse SuperBlock
@@ -241,27 +675,43 @@ impl<'b> Squashfs<'b> {
             ),
         )?;
 
-        let block_size = superblock.block_size;
-        let power_of_two = block_size != 0 && (block_size & (block_size - 1)) == 0;
-        if !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size) || !power_of_two {
-            error!("block_size({:#02x}) invalid", superblock.block_size);
-            return Err(BackhandError::CorruptedOrInvalidSquashfs);
-        }
-
-        if (superblock.block_size as f32).log2() != superblock.block_log as f32 {
-            error!("block size.log2() != block_log");
-            return Err(BackhandError::CorruptedOrInvalidSquashfs);
+        // `block_log_consistent` also ensures `block_size` is a power of two
+        let SuperBlock { block_size, block_log, .. } = superblock;
+        if !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size)
+            || !superblock.block_log_consistent()
+        {
+            error!("block_size({block_size:#02x}) invalid with block_log({block_log})");
+            return Err(BackhandError::CorruptSuperblock { block_size, block_log });
         }
 
         // Parse Compression Options, if any
         info!("Reading Compression options");
-        let compression_options = if superblock.compressor != Compressor::None
+        if superblock.compressor == Compressor::None && superblock.compressor_options_are_present()
+        {
+            warn!("compressor is none, but compression options are flagged as present, ignoring");
+            warnings.push(ReadWarning::CompressionOptionsWithoutCompressor);
+        }
+        let (compression_options, compression_options_raw) = if superblock.compressor
+            != Compressor::None
             && superblock.compressor_options_are_present()
         {
             let bytes = metadata::read_block(reader, &superblock, kind)?;
+            match compression_options_size(superblock.compressor) {
+                Some(size) if size.contains(&bytes.len()) => (),
+                size => {
+                    let (len, compressor) = (bytes.len(), superblock.compressor);
+                    warn!(
+                        "{len} bytes of compression options, expected {size:?} for {compressor:?}"
+                    );
+                    warnings.push(ReadWarning::CompressionOptionsSize { compressor, size: len });
+                }
+            }
             // data -> compression options
             let bv = BitVec::from_slice(&bytes);
-            match CompressionOptions::read(&bv, (kind.inner.type_endian, superblock.compressor)) {
+            let compression_options = match CompressionOptions::read(
+                &bv,
+                (kind.inner.type_endian, superblock.compressor),
+            ) {
                 Ok(co) => {
                     if !co.0.is_empty() {
                         error!("invalid compression options, bytes left over, using");
@@ -272,13 +722,14 @@ impl<'b> Squashfs<'b> {
                     error!("invalid compression options: {e:?}[{bytes:02x?}], not using");
                     None
                 }
-            }
+            };
+            (compression_options, Some(bytes))
         } else {
-            None
+            (None, None)
         };
         info!("compression_options: {compression_options:02x?}");
 
-        Ok((superblock, compression_options))
+        Ok((superblock, compression_options, compression_options_raw))
     }
 
     /// Create `Squashfs` from `Read`er, with the resulting squashfs having read all fields needed
@@ -298,6 +749,113 @@ impl<'b> Squashfs<'b> {
         Self::from_reader_with_offset_and_kind(reader, offset, Kind { inner: Arc::new(LE_V4_0) })
     }
 
+    /// Open the image at `path`, transparently decompressing a gzip, xz, or zstd wrapper such as
+    /// `image.squashfs.xz`
+    ///
+    /// The wrapper is detected by its magic. A wrapped image is decompressed fully into memory
+    /// before parsing, needing as much memory as the size of the inner image, but no temporary
+    /// files. Images without a wrapper are read directly from the file. Returns
+    /// [`BackhandError::UnsupportedCompression`] if the feature for the wrapper is not enabled.
+    pub fn from_path_auto<P: AsRef<Path>>(path: P) -> Result<Self, BackhandError> {
+        let mut file = BufReader::new(File::open(path)?);
+        let magic = file.fill_buf()?;
+
+        let wrapper = if magic.starts_with(&[0x1f, 0x8b]) {
+            Compressor::Gzip
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compressor::Xz
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compressor::Zstd
+        } else {
+            return Self::from_reader(file);
+        };
+
+        info!("decompressing {wrapper:?} wrapper");
+        // every arm is a value, so no code is unreachable without the features
+        #[allow(clippy::match_single_binding)]
+        let image: Result<Vec<u8>, BackhandError> = match wrapper {
+            #[cfg(feature = "gzip")]
+            Compressor::Gzip => {
+                let mut image = vec![];
+                flate2::bufread::MultiGzDecoder::new(file).read_to_end(&mut image)?;
+                Ok(image)
+            }
+            #[cfg(feature = "xz")]
+            Compressor::Xz => {
+                let mut image = vec![];
+                xz2::bufread::XzDecoder::new_multi_decoder(file).read_to_end(&mut image)?;
+                Ok(image)
+            }
+            #[cfg(feature = "zstd")]
+            Compressor::Zstd => {
+                let mut image = vec![];
+                zstd::stream::read::Decoder::with_buffer(file)?.read_to_end(&mut image)?;
+                Ok(image)
+            }
+            _ => Err(BackhandError::UnsupportedCompression(wrapper)),
+        };
+        Self::from_reader(Cursor::new(image?))
+    }
+
+    /// Scan `reader` for the squashfs magic, parsing the image at the first offset accepted by
+    /// `locate`
+    ///
+    /// `locate` is called with the offset of each candidate magic in order, and can for example
+    /// validate a vendor header in front of the image. Returns the parsed image and its offset.
+    ///
+    /// Uses default [`Kind`]: [`LE_V4_0`]
+    pub fn from_reader_locate<R, F>(
+        mut reader: R,
+        mut locate: F,
+    ) -> Result<(Self, u64), BackhandError>
+    where
+        R: BufReadSeek + 'b,
+        F: FnMut(u64) -> bool,
+    {
+        let kind = Kind { inner: Arc::new(LE_V4_0) };
+        let found = scan_magic(&mut reader, &kind.inner.magic, 1, |_, offset| locate(offset))?;
+        let offset = found.ok_or(BackhandError::SuperblockNotFound)?;
+        info!("located image at {offset:#x}");
+        reader.rewind()?;
+        let squashfs = Self::from_reader_with_offset_and_kind(reader, offset, kind)?;
+        Ok((squashfs, offset))
+    }
+
+    /// Scan `reader` for the first plausible superblock at a multiple of 4 bytes, returning its
+    /// offset for [`Self::from_reader_with_offset`]
+    ///
+    /// Useful for firmware dumps with the image after a bootloader or vendor header. A magic is
+    /// only accepted if followed by a superblock of version 4.0 with a valid block size and
+    /// block log. Returns `None` if no superblock is found, or `reader` fails.
+    ///
+    /// Uses default [`Kind`]: [`LE_V4_0`]
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::fs::File;
+    /// # use std::io::BufReader;
+    /// # use backhand::Squashfs;
+    /// let mut file = BufReader::new(File::open("firmware.bin").unwrap());
+    /// let offset = Squashfs::find_offset(&mut file).unwrap();
+    /// let squashfs = Squashfs::from_reader_with_offset(file, offset).unwrap();
+    /// ```
+    pub fn find_offset<R: Read + Seek>(reader: &mut R) -> Option<u64> {
+        Self::find_offset_with_stride(reader, 4)
+    }
+
+    /// Same as [`Self::find_offset`], but checking every multiple of `stride` bytes
+    ///
+    /// A `stride` of `1` checks every offset, `0` is treated as `1`.
+    pub fn find_offset_with_stride<R: Read + Seek>(reader: &mut R, stride: u64) -> Option<u64> {
+        let kind = Kind { inner: Arc::new(LE_V4_0) };
+        let offset = scan_magic(reader, &kind.inner.magic, stride.max(1), |reader, offset| {
+            plausible_superblock(reader, offset, &kind)
+        })
+        .ok()??;
+        info!("found superblock at {offset:#x}");
+        Some(offset)
+    }
+
     /// Same as [`Self::from_reader_with_offset`], but including custom `kind`
     pub fn from_reader_with_offset_and_kind(
         reader: impl BufReadSeek + 'b,
@@ -310,15 +868,17 @@ impl<'b> Squashfs<'b> {
             let reader = SquashfsReaderWithOffset::new(reader, offset)?;
             Box::new(reader)
         };
-        Self::inner_from_reader_with_offset_and_kind(reader, kind)
+        Self::inner_from_reader_with_offset_and_kind(reader, offset, kind)
     }
 
     fn inner_from_reader_with_offset_and_kind(
         mut reader: Box<dyn BufReadSeek + 'b>,
+        offset: u64,
         kind: Kind,
     ) -> Result<Self, BackhandError> {
-        let (superblock, compression_options) =
-            Self::superblock_and_compression_options(&mut reader, &kind)?;
+        let mut warnings = vec![];
+        let (superblock, compression_options, compression_options_raw) =
+            Self::superblock_and_raw_compression_options(&mut reader, &kind, &mut warnings)?;
 
         // Check if legal image
         let total_length = reader.seek(SeekFrom::End(0))?;
@@ -343,40 +903,60 @@ impl<'b> Squashfs<'b> {
         }
 
         // check optional fields
-        if superblock.xattr_table != NOT_SET && superblock.xattr_table > total_length {
-            error!("corrupted or invalid frag_table");
+        if superblock.no_xattrs_in_archive() && superblock.xattr_table_offset().is_some() {
+            warn!("xattr_table is set, but the no xattrs flag is also set, ignoring xattr_table");
+            warnings.push(ReadWarning::XattrTableWithNoXattrsFlag);
+        }
+        if matches!(superblock.xattr_table_in_use(), Some(offset) if offset > total_length) {
+            error!("corrupted or invalid xattr_table");
             return Err(BackhandError::CorruptedOrInvalidSquashfs);
         }
-        if superblock.frag_table != NOT_SET && superblock.frag_table > total_length {
+        if matches!(superblock.frag_table_offset(), Some(offset) if offset > total_length) {
             error!("corrupted or invalid frag_table");
             return Err(BackhandError::CorruptedOrInvalidSquashfs);
         }
-        if superblock.export_table != NOT_SET && superblock.export_table > total_length {
+        if matches!(superblock.export_table_offset(), Some(offset) if offset > total_length) {
             error!("corrupted or invalid export_table");
             return Err(BackhandError::CorruptedOrInvalidSquashfs);
         }
 
         // Read all fields from filesystem to make a Squashfs
         info!("Reading Inodes");
-        let inodes = reader.inodes(&superblock, &kind)?;
+        let inode_table = reader.inodes(&superblock, &kind)?;
 
         info!("Reading Root Inode");
-        let root_inode = reader.root_inode(&superblock, &kind)?;
+        let root_inode =
+            if superblock.inode_count == 0 && superblock.inode_table == superblock.dir_table {
+                // nothing to read, present as an empty filesystem
+                warn!("inode_count is zero and the inode table is empty, using an empty root");
+                empty_root_inode(&superblock)
+            } else {
+                reader.root_inode(&superblock, &kind)?
+            };
 
         info!("Reading Fragments");
         let fragments = reader.fragments(&superblock, &kind)?;
         let fragment_ptr = fragments.as_ref().map(|frag| frag.0);
+        let fragment_blocks = fragments.as_ref().map_or(0, |frag| frag.2);
         let fragment_table = fragments.map(|a| a.1);
 
         info!("Reading Exports");
         let export = reader.export(&superblock, &kind)?;
         let export_ptr = export.as_ref().map(|export| export.0);
+        let export_blocks = export.as_ref().map_or(0, |export| export.2);
         let export_table = export.map(|a| a.1);
 
         info!("Reading Ids");
         let id = reader.id(&superblock, &kind)?;
         let id_ptr = id.0;
         let id_table = id.1;
+        let id_blocks = id.2;
+        if superblock.id_count == 0 {
+            warnings.push(ReadWarning::EmptyIdTable);
+        }
+
+        info!("Reading Xattrs");
+        let xattrs = reader.xattrs(&superblock, &kind)?;
 
         let last_dir_position = if let Some(fragment_ptr) = fragment_ptr {
             trace!("using fragment for end of dir");
@@ -389,20 +969,54 @@ impl<'b> Squashfs<'b> {
             id_ptr
         };
 
+        // Only read the dir table as far as the directories need, as other data such as fragments
+        // could be stored between the dir table and the following table
+        let dir_extents: Vec<(u64, usize)> = inode_table
+            .inodes
+            .values()
+            .chain(std::iter::once(&root_inode))
+            .filter_map(|inode| inode.as_dir().ok())
+            .filter(|dir| dir.file_size() >= 4)
+            .map(|dir| {
+                let needed = usize::from(dir.block_offset()) + dir.file_size() as usize - 3;
+                (u64::from(dir.block_index()), needed)
+            })
+            .collect();
+
         info!("Reading Dirs");
-        let dir_blocks = reader.dir_blocks(&superblock, last_dir_position, &kind)?;
+        let dir_blocks = reader.dir_blocks(&superblock, last_dir_position, &dir_extents, &kind)?;
+
+        let table_block_counts = TableBlockCounts {
+            inode: inode_table.metadata_offsets.len(),
+            dir: dir_blocks.len(),
+            fragment: fragment_blocks,
+            export: export_blocks,
+            id: id_blocks,
+        };
 
         let squashfs = Squashfs {
             kind,
             superblock,
             compression_options,
-            inodes,
+            compression_options_raw,
+            warnings,
+            inodes: inode_table.inodes,
+            inode_refs: inode_table.refs,
             root_inode,
             dir_blocks,
             fragments: fragment_table,
             export: export_table,
             id: id_table,
+            xattrs,
             file: reader,
+            max_dir_depth: DEFAULT_MAX_DIR_DEPTH,
+            cache_capacity: None,
+            offset,
+            inode_ranges: inode_table.ranges,
+            inode_metadata_offsets: inode_table.metadata_offsets,
+            inode_numbers_by_ref: inode_table.by_ref,
+            table_block_counts,
+            strict: false,
         };
 
         // show info about flags
@@ -438,6 +1052,10 @@ impl<'b> Squashfs<'b> {
             info!("flag: xattrs are stored uncompressed");
         }
 
+        if superblock.no_xattrs_in_archive() {
+            info!("flag: no xattrs in archive");
+        }
+
         if superblock.compressor_options_are_present() {
             info!("flag: compressor options are present");
         }
@@ -462,85 +1080,142 @@ impl<'b> Squashfs<'b> {
             return Ok(None);
         }
 
-        // ignore blocks before our block_index, grab all the rest of the bytes
-        // TODO: perf
-        let block: Vec<u8> = self
-            .dir_blocks
-            .iter()
-            .filter(|(a, _)| *a >= block_index)
-            .flat_map(|(_, b)| b.iter())
-            .copied()
-            .collect();
+        // ignore blocks before our block_index, grab only the blocks containing this dir
+        let needed = block_offset + file_size as usize - 3;
+        let first = self.dir_blocks.partition_point(|(a, _)| *a < block_index);
+        // directories are always stored in metadata blocks of the directory table, never in
+        // fragments or data blocks
+        if !matches!(self.dir_blocks.get(first), Some((start, _)) if *start == block_index) {
+            error!("directory at {block_index:#x} is not the start of a directory table block");
+            return Err(BackhandError::DirectoryNotInDirTable);
+        }
+        // the blocks are already decompressed, only copy them if the listing spans multiple
+        let block: Cow<[u8]> = match &self.dir_blocks[first..] {
+            [(_, b), ..] if b.len() >= needed => Cow::Borrowed(b.as_slice()),
+            blocks => {
+                let mut block = Vec::with_capacity(needed);
+                for (_, b) in blocks {
+                    if block.len() >= needed {
+                        break;
+                    }
+                    block.extend_from_slice(b);
+                }
+                Cow::Owned(block)
+            }
+        };
+        if block.len() < needed {
+            error!("directory at {block_index:#x} extends past the end of the directory table");
+            return Err(BackhandError::DirectoryNotInDirTable);
+        }
 
         let bytes = &block[block_offset..][..file_size as usize - 3];
         let mut dirs = vec![];
         let mut all_bytes = bytes.view_bits::<Msb0>();
-        // Read until we fail to turn bytes into `T`
-        while let Ok((rest, t)) = Dir::read(all_bytes, self.kind.inner.type_endian) {
-            dirs.push(t);
-            all_bytes = rest;
+        // the listing is exactly `file_size - 3` bytes, anything not parsed is corrupted, such as
+        // an entry of an unknown type
+        while !all_bytes.is_empty() {
+            let offset = bytes.len() - all_bytes.len() / 8;
+            match Dir::read(all_bytes, self.kind.inner.type_endian) {
+                Ok((rest, t)) => {
+                    dirs.push(t);
+                    all_bytes = rest;
+                }
+                Err(error) => {
+                    error!("directory at {block_index:#x}+{block_offset:#x}: {error}");
+                    return Err(BackhandError::CorruptDirectory { block_index, offset, error });
+                }
+            }
         }
 
         trace!("finish");
         Ok(Some(dirs))
     }
 
+    /// Inode number of a dir entry: the number of its header plus the signed offset of the entry
+    ///
+    /// Must be within `1..=inode_count`, unless `inode_count` is zero.
+    fn resolve_inode_number(&self, base: u32, offset: i16) -> Result<u32, BackhandError> {
+        let inode_num = i64::from(base) + i64::from(offset);
+        let max = match self.superblock.inode_count {
+            0 => i64::from(u32::MAX),
+            count => i64::from(count),
+        };
+        if !(1..=max).contains(&inode_num) {
+            error!("dir entry inode number {base} + {offset} out of range");
+            return Err(BackhandError::InvalidInodeNumber(inode_num));
+        }
+        Ok(inode_num as u32)
+    }
+
+    /// Walk the directory tree starting at `root_inode`, pushing all found nodes into `root`
+    ///
+    /// This uses a work-list instead of recursion, so that a deeply nested tree can't overflow
+    /// the stack.
     fn extract_dir(
         &self,
-        fullpath: &mut PathBuf,
         root: &mut Nodes<SquashfsFileReader>,
-        dir_inode: &Inode,
+        root_inode: &Inode,
         id_table: &[Id],
+        reachable: &mut FxHashSet<u32>,
     ) -> Result<(), BackhandError> {
-        let dirs = match &dir_inode.inner {
-            InodeInner::BasicDirectory(basic_dir) => {
-                trace!("BASIC_DIR inodes: {:02x?}", basic_dir);
-                self.dir_from_index(
-                    basic_dir.block_index.try_into().unwrap(),
-                    basic_dir.file_size.try_into().unwrap(),
-                    basic_dir.block_offset as usize,
-                )?
-            }
-            InodeInner::ExtendedDirectory(ext_dir) => {
-                trace!("EXT_DIR: {:#02x?}", ext_dir);
-                self.dir_from_index(
-                    ext_dir.block_index.try_into().unwrap(),
-                    ext_dir.file_size,
-                    ext_dir.block_offset as usize,
-                )?
-            }
-            _ => return Err(BackhandError::UnexpectedInode(dir_inode.inner.clone())),
-        };
-        if let Some(dirs) = dirs {
+        // a directory is only entered once, even if referenced again by a later entry
+        let mut visited_dirs = FxHashSet::default();
+        visited_dirs.insert(root_inode.header.inode_number);
+        let mut work = vec![(PathBuf::from("/"), root_inode, 0)];
+        while let Some((fullpath, dir_inode, depth)) = work.pop() {
+            let dir = dir_inode.as_dir()?;
+            trace!("DIR: {:#02x?}", dir);
+            let dirs = self.dir_from_index(
+                u64::from(dir.block_index()),
+                dir.file_size(),
+                dir.block_offset() as usize,
+            )?;
+            let Some(dirs) = dirs else {
+                continue;
+            };
             for d in &dirs {
                 trace!("extracing entry: {:#?}", d.dir_entries);
                 for entry in &d.dir_entries {
-                    let inode_key =
-                        (d.inode_num as i32 + entry.inode_offset as i32).try_into().unwrap();
-                    let found_inode = &self.inodes[&inode_key];
+                    let inode_key = self.resolve_inode_number(d.inode_num, entry.inode_offset)?;
+                    reachable.insert(inode_key);
+                    let found_inode = self
+                        .inodes
+                        .get(&inode_key)
+                        .ok_or(BackhandError::InvalidInodeNumber(i64::from(inode_key)))?;
                     let header = found_inode.header;
-                    fullpath.push(entry.name()?);
+                    let mut path = fullpath.join(entry.name()?);
 
                     let inner: InnerNode<SquashfsFileReader> = match entry.t {
                         // BasicDirectory, ExtendedDirectory
                         InodeId::BasicDirectory | InodeId::ExtendedDirectory => {
-                            // its a dir, extract all children inodes
-                            self.extract_dir(fullpath, root, found_inode, &self.id)?;
+                            // its a dir, extract all children inodes later
+                            if !visited_dirs.insert(inode_key) {
+                                self.recover(ReadWarning::DirectoryReferencedAgain {
+                                    inode_number: inode_key,
+                                })?;
+                                warn!("skipping {}, directory already visited", path.display());
+                                continue;
+                            }
+                            if depth >= self.max_dir_depth {
+                                error!("directory depth exceeds {}", self.max_dir_depth);
+                                return Err(BackhandError::MaxDepthExceeded);
+                            }
+                            work.push((path.clone(), found_inode, depth + 1));
                             InnerNode::Dir(SquashfsDir::default())
                         }
                         // BasicFile
                         InodeId::BasicFile => {
                             trace!("before_file: {:#02x?}", entry);
-                            let basic = match &found_inode.inner {
-                                InodeInner::BasicFile(file) => file.clone(),
-                                InodeInner::ExtendedFile(file) => file.into(),
+                            let (basic, sparse) = match &found_inode.inner {
+                                InodeInner::BasicFile(file) => (file.clone(), 0),
+                                InodeInner::ExtendedFile(file) => (file.into(), file.sparse),
                                 _ => {
                                     return Err(BackhandError::UnexpectedInode(
                                         found_inode.inner.clone(),
                                     ))
                                 }
                             };
-                            InnerNode::File(SquashfsFileReader { basic })
+                            InnerNode::File(SquashfsFileReader { basic, sparse })
                         }
                         // Basic Symlink
                         InodeId::BasicSymlink => {
@@ -561,13 +1236,12 @@ impl<'b> Squashfs<'b> {
                             return Err(BackhandError::UnsupportedInode(found_inode.inner.clone()))
                         }
                     };
-                    let node = Node::new(
-                        fullpath.clone(),
-                        NodeHeader::from_inode(header, id_table),
-                        inner,
-                    );
+                    // `join` over-allocates, adding up for images with many files
+                    path.shrink_to_fit();
+                    let mut node = Node::new(path, self.node_header(header, id_table)?, inner);
+                    self.check_xattr_index(found_inode)?;
+                    node.inode_number = Some(inode_key);
                     root.nodes.push(node);
-                    fullpath.pop();
                 }
             }
         }
@@ -575,6 +1249,309 @@ impl<'b> Squashfs<'b> {
         Ok(())
     }
 
+    /// Split into all owned components, including the reader of the image
+    ///
+    /// For building custom processing on top of the parsed tables, when the other methods of
+    /// `Self` are not enough.
+    pub fn into_parts(self) -> SquashfsParts<'b> {
+        SquashfsParts {
+            kind: self.kind,
+            superblock: self.superblock,
+            compression_options: self.compression_options,
+            inodes: self.inodes,
+            inode_refs: self.inode_refs,
+            root_inode: self.root_inode,
+            dir_blocks: self.dir_blocks,
+            fragments: self.fragments,
+            export: self.export,
+            id: self.id,
+            xattrs: self.xattrs,
+            reader: self.file,
+            offset: self.offset,
+        }
+    }
+
+    /// Amount of metadata blocks of each table, as counted while reading the image
+    pub fn table_block_counts(&self) -> TableBlockCounts {
+        self.table_block_counts
+    }
+
+    /// Mutable access to the [`SuperBlock`], for patching with [`Self::write_superblock`]
+    ///
+    /// Fields such as `mod_time` are safe to modify. Table pointers, counts, `bytes_used`,
+    /// `root_inode`, `block_size`, `block_log`, `compressor`, and the flags describing how data
+    /// is stored must stay consistent with the image, or it will be corrupted.
+    pub fn superblock_mut(&mut self) -> &mut SuperBlock {
+        &mut self.superblock
+    }
+
+    /// Write the current [`SuperBlock`] into `out`, at the same offset it was read from
+    ///
+    /// The rest of the image is left untouched, so `out` is usually the original image opened
+    /// for writing.
+    pub fn write_superblock<W: Write + Seek>(&self, out: &mut W) -> Result<(), BackhandError> {
+        let bytes = self.superblock.to_bytes(&self.kind)?;
+        out.seek(SeekFrom::Start(self.offset))?;
+        out.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Bytes appended after the image, such as a signature
+    ///
+    /// The image is expected to be padded to [`DEFAULT_PAD_LEN`] after `bytes_used`, as done by
+    /// `mksquashfs` and [`crate::FilesystemWriter`]. Everything from the end of that padding to
+    /// the end of the file is returned, empty if nothing was appended.
+    pub fn trailing_data(&mut self) -> Result<Vec<u8>, BackhandError> {
+        let pad_len = u64::from(DEFAULT_PAD_LEN);
+        let padded_end = self
+            .superblock
+            .bytes_used
+            .checked_add(pad_len - 1)
+            .ok_or(BackhandError::CorruptedOrInvalidSquashfs)?
+            / pad_len
+            * pad_len;
+        let end = self.file.seek(SeekFrom::End(0))?;
+        if end <= padded_end {
+            return Ok(vec![]);
+        }
+        self.file.seek(SeekFrom::Start(padded_end))?;
+        let mut data = vec![];
+        self.file.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// Parsed index of the directory `dir_inode`, used for faster lookup of names
+    ///
+    /// Only extended directories store an index, returning an empty index for basic directories,
+    /// and [`BackhandError::UnexpectedInode`] if `dir_inode` is not a directory.
+    pub fn directory_index(&self, dir_inode: &Inode) -> Result<Vec<DirIndexEntry>, BackhandError> {
+        match dir_inode.as_dir()? {
+            DirectoryInode::Basic(_) => Ok(vec![]),
+            DirectoryInode::Extended(dir) => {
+                Ok(dir.dir_index.iter().map(DirIndexEntry::from).collect())
+            }
+        }
+    }
+
+    /// Entries of the directory `dir_inode`, without reading the rest of the tree
+    ///
+    /// Only the listing of `dir_inode` is parsed, inodes are resolved as the iterator advances.
+    /// Start from [`Self::root_inode`], and descend by calling this again with the inode of an
+    /// entry. Yields a single [`BackhandError::UnexpectedInode`] if `dir_inode` is not a
+    /// directory.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::fs::File;
+    /// # use std::io::BufReader;
+    /// # use backhand::Squashfs;
+    /// let file = BufReader::new(File::open("image.squashfs").unwrap());
+    /// let squashfs = Squashfs::from_reader(file).unwrap();
+    /// for entry in squashfs.read_dir(&squashfs.root_inode) {
+    ///     let entry = entry.unwrap();
+    ///     println!("{:?}: {}", entry.name, entry.inode_number);
+    /// }
+    /// ```
+    pub fn read_dir<'a>(
+        &'a self,
+        dir_inode: &Inode,
+    ) -> impl Iterator<Item = Result<ReadDirEntry<'a>, BackhandError>> + 'a {
+        let dirs = dir_inode.as_dir().and_then(|dir| {
+            self.dir_from_index(
+                u64::from(dir.block_index()),
+                dir.file_size(),
+                dir.block_offset() as usize,
+            )
+        });
+        let (dirs, error) = match dirs {
+            Ok(dirs) => (dirs.unwrap_or_default(), None),
+            Err(e) => (vec![], Some(Err(e))),
+        };
+        let entries = dirs.into_iter().flat_map(move |dir| {
+            let base = dir.inode_num;
+            dir.dir_entries.into_iter().map(move |entry| {
+                let inode_number = self.resolve_inode_number(base, entry.inode_offset)?;
+                let inode = self
+                    .inodes
+                    .get(&inode_number)
+                    .ok_or(BackhandError::InvalidInodeNumber(i64::from(inode_number)))?;
+                let name = entry.name()?.as_os_str().to_os_string();
+                Ok(ReadDirEntry { name, inode_number, inode })
+            })
+        });
+        error.into_iter().chain(entries)
+    }
+
+    /// Recoverable problems found while reading the image, in the order found
+    ///
+    /// These are also logged as warnings. Empty for a well formed image.
+    pub fn warnings(&self) -> &[ReadWarning] {
+        &self.warnings
+    }
+
+    /// Find the inode located at `inode_ref` in the inode table, such as referenced from a
+    /// directory entry or the export table
+    ///
+    /// Uses the locations as read from the image, changes to [`Self::inode_refs`] aren't seen.
+    pub fn inode_by_ref(&self, inode_ref: InodeRef) -> Option<&Inode> {
+        self.inodes.get(self.inode_numbers_by_ref.get(&inode_ref)?)
+    }
+
+    /// Find the inode numbered `inode_number` through the export table, as done to resolve NFS
+    /// file handles
+    ///
+    /// Returns [`BackhandError::MissingExportTable`] if the image has no export table, or the
+    /// `NFSExportTableExists` flag isn't set, and [`BackhandError::InvalidInodeNumber`] if the
+    /// export table has no entry for `inode_number`, or its entry doesn't reference an inode.
+    pub fn export_lookup(&self, inode_number: u32) -> Result<&Inode, BackhandError> {
+        let export = match &self.export {
+            Some(export) if self.superblock.nfs_export_table_exists() => export,
+            _ => return Err(BackhandError::MissingExportTable),
+        };
+        let invalid = || BackhandError::InvalidInodeNumber(i64::from(inode_number));
+        // inode numbers start at 1
+        let index = inode_number.checked_sub(1).ok_or_else(invalid)?;
+        let inode_ref = InodeRef(export.get(index as usize).ok_or_else(invalid)?.num);
+
+        // the entry of a consistent table references the inode with the same number
+        if self.inode_refs.get(&inode_number) == Some(&inode_ref) {
+            return self.inodes.get(&inode_number).ok_or_else(invalid);
+        }
+        self.recover(ReadWarning::ExportEntryMismatch { inode_number })?;
+        warn!("export entry {inode_number} references another inode");
+        self.inode_by_ref(inode_ref).ok_or_else(invalid)
+    }
+
+    /// Uncompressed bytes of the compression options, exactly as stored in the image
+    ///
+    /// Present even if the bytes couldn't be parsed into [`Self::compression_options`], such as
+    /// with trailing bytes. Writing these as is keeps the options of a repacked image identical.
+    pub fn compression_options_raw(&self) -> Option<&[u8]> {
+        self.compression_options_raw.as_deref()
+    }
+
+    /// [`Self::compression_options`] if present and of [`Compressor::Gzip`]
+    pub fn gzip_options(&self) -> Option<&Gzip> {
+        self.compression_options.as_ref()?.as_gzip()
+    }
+
+    /// [`Self::compression_options`] if present and of [`Compressor::Lzo`]
+    pub fn lzo_options(&self) -> Option<&Lzo> {
+        self.compression_options.as_ref()?.as_lzo()
+    }
+
+    /// [`Self::compression_options`] if present and of [`Compressor::Xz`]
+    pub fn xz_options(&self) -> Option<&Xz> {
+        self.compression_options.as_ref()?.as_xz()
+    }
+
+    /// [`Self::compression_options`] if present and of [`Compressor::Lz4`]
+    pub fn lz4_options(&self) -> Option<&Lz4> {
+        self.compression_options.as_ref()?.as_lz4()
+    }
+
+    /// [`Self::compression_options`] if present and of [`Compressor::Zstd`]
+    pub fn zstd_options(&self) -> Option<&Zstd> {
+        self.compression_options.as_ref()?.as_zstd()
+    }
+
+    /// Cross-reference the export table against the parsed inode table
+    ///
+    /// Entry `n` of the export table must reference the inode numbered `n + 1`. Returns the inode
+    /// numbers of all entries referencing a missing inode, or one with another inode number. Images
+    /// without an export table are always consistent.
+    pub fn verify_export_consistency(&self) -> Result<(), Vec<u32>> {
+        let Some(export) = &self.export else {
+            return Ok(());
+        };
+        let inconsistent: Vec<u32> = export
+            .iter()
+            .zip(1..)
+            .filter(|(entry, inode_num)| {
+                let found = self.inode_numbers_by_ref.get(&InodeRef(entry.num));
+                if found != Some(inode_num) {
+                    error!("export entry {inode_num} references {:#x}: {found:?}", entry.num);
+                    return true;
+                }
+                false
+            })
+            .map(|(_, inode_num)| inode_num)
+            .collect();
+
+        if inconsistent.is_empty() {
+            Ok(())
+        } else {
+            Err(inconsistent)
+        }
+    }
+
+    /// Uncompressed bytes of inode `inode_num`, exactly as stored in the inode table
+    pub fn raw_inode_bytes(&mut self, inode_num: u32) -> Result<Vec<u8>, BackhandError> {
+        let range = self.inode_ranges.get(&inode_num).ok_or(BackhandError::FileNotFound)?.clone();
+
+        // last metadata block starting before the inode
+        let index = self
+            .inode_metadata_offsets
+            .partition_point(|(_, block_start)| *block_start <= range.start)
+            .saturating_sub(1);
+        let (block_offset, block_start) =
+            *self.inode_metadata_offsets.get(index).ok_or(BackhandError::Unreachable)?;
+
+        self.file.seek(SeekFrom::Start(self.superblock.inode_table + block_offset))?;
+        let mut bytes = vec![];
+        while block_start + bytes.len() < range.end {
+            let mut block = metadata::read_block(&mut self.file, &self.superblock, &self.kind)?;
+            if block.is_empty() {
+                return Err(BackhandError::CorruptedOrInvalidSquashfs);
+            }
+            bytes.append(&mut block);
+        }
+
+        Ok(bytes[range.start - block_start..range.end - block_start].to_vec())
+    }
+
+    /// Returns [`BackhandError::Strict`] for `warning` if opened in strict mode, see
+    /// [`SquashfsReaderBuilder::strict`]
+    fn recover(&self, warning: ReadWarning) -> Result<(), BackhandError> {
+        if self.strict {
+            error!("rejected by strict mode: {warning:?}");
+            return Err(BackhandError::Strict(warning));
+        }
+        Ok(())
+    }
+
+    /// [`NodeHeader`] of an inode, with its uid and gid looked up in `id_table`
+    fn node_header(
+        &self,
+        header: InodeHeader,
+        id_table: &[Id],
+    ) -> Result<NodeHeader, BackhandError> {
+        for index in [header.uid, header.gid] {
+            if usize::from(index) >= id_table.len() {
+                let inode_number = header.inode_number;
+                self.recover(ReadWarning::IdIndexOutOfRange { inode_number, index })?;
+            }
+        }
+        Ok(NodeHeader::from_inode(header, id_table))
+    }
+
+    /// Set the maximum depth of nested directories, before returning
+    /// [`BackhandError::MaxDepthExceeded`] from [`Self::into_filesystem_reader`]
+    ///
+    /// Defaults to [`DEFAULT_MAX_DIR_DEPTH`].
+    pub fn set_max_dir_depth(&mut self, max_dir_depth: usize) {
+        self.max_dir_depth = max_dir_depth;
+    }
+
+    /// Set the maximum amount of decompressed fragment blocks kept in memory by the
+    /// [`FilesystemReader`] created from `self`
+    ///
+    /// Once full, the least recently used fragment block is dropped for each newly read one. `0`
+    /// disables the cache. Defaults to unbounded, keeping every fragment block read.
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        self.cache_capacity = Some(capacity);
+    }
+
     /// Symlink Details
     ///
     /// # Returns
@@ -589,6 +1566,27 @@ impl<'b> Squashfs<'b> {
         Err(BackhandError::FileNotFound)
     }
 
+    /// Check that the xattr index of `inode`, if any, is in the xattr table
+    fn check_xattr_index(&self, inode: &Inode) -> Result<(), BackhandError> {
+        let Some(index) = inode.metadata().xattr_index else {
+            return Ok(());
+        };
+        let Some(xattrs) = &self.xattrs else {
+            let inode_number = inode.header.inode_number;
+            self.recover(ReadWarning::XattrIndexWithoutTable { inode_number })?;
+            warn!(
+                "inode {} has an xattr index, but there is no xattr table",
+                inode.header.inode_number
+            );
+            return Ok(());
+        };
+        if xattrs.get(index as usize).is_none() {
+            error!("xattr index {index} is outside of the xattr table");
+            return Err(BackhandError::CorruptedOrInvalidSquashfs);
+        }
+        Ok(())
+    }
+
     /// Char Device Details
     ///
     /// # Returns
@@ -618,12 +1616,79 @@ impl<'b> Squashfs<'b> {
     /// Convert into [`FilesystemReader`] by extracting all file bytes and converting into a filesystem
     /// like structure in-memory
     pub fn into_filesystem_reader(self) -> Result<FilesystemReader<'b>, BackhandError> {
-        info!("creating fs tree");
-        let mut root = Nodes::new_root(NodeHeader::from_inode(self.root_inode.header, &self.id));
-        self.extract_dir(&mut PathBuf::from("/"), &mut root, &self.root_inode, &self.id)?;
-        root.nodes.sort();
+        let (filesystem, _) = self.inner_into_filesystem_reader()?;
+        Ok(filesystem)
+    }
+
+    /// Same as [`Self::into_filesystem_reader`], but also return [`OpenDiagnostics`]
+    ///
+    /// Useful for detecting images where the inode table doesn't match the directory tree,
+    /// a sign of corruption or unusual packing.
+    pub fn into_filesystem_reader_diagnostic(
+        self,
+    ) -> Result<(FilesystemReader<'b>, OpenDiagnostics), BackhandError> {
+        let superblock_inode_count = self.superblock.inode_count;
+        let parsed_inode_count = self.inodes.len();
+        let all_inodes: Vec<u32> = self.inodes.keys().copied().collect();
+        let (filesystem, reachable) = self.inner_into_filesystem_reader()?;
+
+        let mut unreachable_inodes: Vec<u32> =
+            all_inodes.into_iter().filter(|num| !reachable.contains(num)).collect();
+        unreachable_inodes.sort_unstable();
+
+        let diagnostics =
+            OpenDiagnostics { superblock_inode_count, parsed_inode_count, unreachable_inodes };
+        Ok((filesystem, diagnostics))
+    }
+
+    /// Same as [`Self::into_filesystem_reader`], but keeping `self` by cloning the parsed metadata
+    ///
+    /// The returned [`FilesystemReader`] reads file data from `reader`, a new handle to the same
+    /// image, such as the file opened again. It must start at the same position as the reader given
+    /// to `self`, the offset of the image is applied again.
+    pub fn filesystem_reader<'c, R: BufReadSeek + 'c>(
+        &self,
+        reader: R,
+    ) -> Result<FilesystemReader<'c>, BackhandError> {
+        let reader: Box<dyn BufReadSeek + 'c> = if self.offset == 0 {
+            Box::new(reader)
+        } else {
+            Box::new(SquashfsReaderWithOffset::new(reader, self.offset)?)
+        };
+        let (root, _) = self.tree()?;
+        let mut inodes = self.inodes.clone();
+        let root_inode_number = self.root_inode.header.inode_number;
+        inodes.entry(root_inode_number).or_insert_with(|| self.root_inode.clone());
+
+        Ok(FilesystemReader {
+            kind: Kind::from_kind(&self.kind),
+            block_size: self.superblock.block_size,
+            block_log: self.superblock.block_log,
+            compressor: self.superblock.compressor,
+            compression_options: self.compression_options,
+            mod_time: self.superblock.mod_time,
+            id_table: self.id.clone(),
+            fragments: self.fragments.clone(),
+            root,
+            inodes,
+            xattr_table: self.xattrs.clone(),
+            reader: Mutex::new(reader),
+            cache: Mutex::new(Cache::new(self.cache_capacity)),
+            decompress_pool: None,
+            bytes_used: self.superblock.bytes_used,
+            advise_fd: None,
+        })
+    }
+
+    /// Returns the [`FilesystemReader`] and the set of inode numbers reachable from the root
+    fn inner_into_filesystem_reader(
+        self,
+    ) -> Result<(FilesystemReader<'b>, FxHashSet<u32>), BackhandError> {
+        let (root, reachable) = self.tree()?;
+        // the root inode isn't in the inode table of an empty filesystem
+        let mut inodes = self.inodes;
+        inodes.entry(self.root_inode.header.inode_number).or_insert(self.root_inode);
 
-        info!("created fs tree");
         let filesystem = FilesystemReader {
             kind: self.kind,
             block_size: self.superblock.block_size,
@@ -634,9 +1699,65 @@ impl<'b> Squashfs<'b> {
             id_table: self.id,
             fragments: self.fragments,
             root,
+            inodes,
+            xattr_table: self.xattrs,
             reader: Mutex::new(Box::new(self.file)),
-            cache: Mutex::new(Cache::default()),
+            cache: Mutex::new(Cache::new(self.cache_capacity)),
+            decompress_pool: None,
+            bytes_used: self.superblock.bytes_used,
+            advise_fd: None,
         };
-        Ok(filesystem)
+        Ok((filesystem, reachable))
+    }
+
+    /// Returns the sorted nodes, and the set of inode numbers reachable from the root
+    fn tree(&self) -> Result<(Nodes<SquashfsFileReader>, FxHashSet<u32>), BackhandError> {
+        info!("creating fs tree");
+        let mut reachable = FxHashSet::default();
+        reachable.insert(self.root_inode.header.inode_number);
+        let mut root = Nodes::new_root(self.node_header(self.root_inode.header, &self.id)?);
+        self.check_xattr_index(&self.root_inode)?;
+        root.root_mut().inode_number = Some(self.root_inode.header.inode_number);
+        self.extract_dir(&mut root, &self.root_inode, &self.id, &mut reachable)?;
+        root.nodes.sort();
+
+        info!("created fs tree");
+        Ok((root, reachable))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let mut cache = Cache::new(Some(2));
+        cache.insert_fragment(0, vec![0]);
+        cache.insert_fragment(1, vec![1]);
+        // `0` is used again, so `1` is evicted
+        assert_eq!(cache.fragment(0), Some(&[0][..]));
+        cache.insert_fragment(2, vec![2]);
+        assert_eq!(cache.fragment(1), None);
+        assert_eq!(cache.fragment(0), Some(&[0][..]));
+        assert_eq!(cache.fragment(2), Some(&[2][..]));
+
+        // inserting again replaces the bytes without evicting
+        cache.insert_fragment(2, vec![3]);
+        assert_eq!(cache.fragment(0), Some(&[0][..]));
+        assert_eq!(cache.fragment(2), Some(&[3][..]));
+        assert_eq!(cache.fragment_cache.len(), 2);
+
+        let mut cache = Cache::new(Some(0));
+        cache.insert_fragment(0, vec![0]);
+        assert_eq!(cache.fragment(0), None);
+
+        // unbounded never evicts
+        let mut cache = Cache::new(None);
+        for start in 0..100 {
+            cache.insert_fragment(start, vec![start as u8]);
+        }
+        assert_eq!(cache.fragment_cache.len(), 100);
+        assert!(cache.fragment_order.is_empty());
     }
 }
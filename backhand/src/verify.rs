@@ -0,0 +1,208 @@
+//! Verify all data of an image
+
+use std::path::PathBuf;
+
+use tracing::{trace, warn};
+
+use crate::data::{BlockLayout, BlockSize};
+use crate::error::BackhandError;
+use crate::filesystem::node::{NodeType, SquashfsFileReader};
+use crate::filesystem::reader::FilesystemReader;
+use crate::fragment::Fragment;
+use crate::inode::{BasicFile, InodeInner};
+use crate::{InnerNode, Node};
+
+const NO_FRAGMENT: u32 = 0xffffffff;
+
+/// Problem found by [`FilesystemReader::deep_verify`]
+#[derive(Debug)]
+pub struct VerifyError {
+    /// Node the problem was found in, `None` for the tables of the image
+    pub path: Option<PathBuf>,
+    pub error: BackhandError,
+}
+
+/// Summary of [`FilesystemReader::deep_verify`]
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Nodes checked against their inode, including the root
+    pub nodes: usize,
+    /// Files with all of their data read
+    pub files: usize,
+    /// Data and fragment blocks decompressed
+    pub blocks: usize,
+    /// Total bytes of file data read
+    pub bytes: u64,
+    /// Every problem found, in the order found
+    pub errors: Vec<VerifyError>,
+}
+
+impl VerifyReport {
+    /// Returns `true` if no problem was found
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl<'b> FilesystemReader<'b> {
+    /// Read and check all data of the image, collecting every problem found into the report
+    ///
+    /// Every entry of the fragment table and every data block of every file is read and
+    /// decompressed, checking that it holds as many bytes as the file needs. Each node is also
+    /// checked against its inode. Data is dropped once checked, only buffers for a single block
+    /// are kept, so memory usage doesn't depend on the size of the image.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::fs::File;
+    /// # use std::io::BufReader;
+    /// # use backhand::FilesystemReader;
+    /// let file = BufReader::new(File::open("image.squashfs").unwrap());
+    /// let filesystem = FilesystemReader::from_reader(file).unwrap();
+    /// let report = filesystem.deep_verify().unwrap();
+    /// for error in &report.errors {
+    ///     eprintln!("{:?}: {}", error.path, error.error);
+    /// }
+    /// assert!(report.is_ok());
+    /// ```
+    pub fn deep_verify(&self) -> Result<VerifyReport, BackhandError> {
+        let mut report = VerifyReport::default();
+        let (mut buf_read, mut buf_decompress) = self.alloc_read_buffers();
+
+        // uncompressed size of each fragment block, `None` if it couldn't be read
+        let mut fragment_sizes = vec![];
+        for (index, fragment) in self.fragments.iter().flatten().enumerate() {
+            match self.verify_fragment(fragment, &mut buf_read, &mut buf_decompress) {
+                Ok(size) => {
+                    report.blocks += 1;
+                    fragment_sizes.push(Some(size));
+                }
+                Err(error) => {
+                    warn!("fragment {index}: {error}");
+                    report.errors.push(VerifyError { path: None, error });
+                    fragment_sizes.push(None);
+                }
+            }
+        }
+
+        for node in &self.root.nodes {
+            trace!("verifying {}", node.fullpath.display());
+            report.nodes += 1;
+            let ret = self.verify_inode(node).and_then(|()| {
+                let InnerNode::File(file) = &node.inner else {
+                    return Ok(());
+                };
+                let blocks = self.verify_file_data(
+                    &file.basic,
+                    &fragment_sizes,
+                    &mut buf_read,
+                    &mut buf_decompress,
+                )?;
+                report.files += 1;
+                report.blocks += blocks;
+                report.bytes += u64::from(file.basic.file_size);
+                Ok(())
+            });
+            if let Err(error) = ret {
+                warn!("{}: {error}", node.fullpath.display());
+                report.errors.push(VerifyError { path: Some(node.fullpath.clone()), error });
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Returns the uncompressed size of the fragment block
+    fn verify_fragment(
+        &self,
+        fragment: &Fragment,
+        buf_read: &mut Vec<u8>,
+        buf_decompress: &mut Vec<u8>,
+    ) -> Result<usize, BackhandError> {
+        let size = fragment.size.size();
+        let end = fragment.start.checked_add(u64::from(size));
+        if !matches!(end, Some(end) if end <= self.bytes_used) || size > self.block_size {
+            return Err(BackhandError::CorruptedOrInvalidSquashfs);
+        }
+        self.read_layout_block(
+            fragment.start,
+            BlockSize::from(fragment.size),
+            buf_read,
+            buf_decompress,
+        )?;
+        Ok(buf_decompress.len())
+    }
+
+    /// Check that the inode of `node` exists and is of the same type
+    fn verify_inode(&self, node: &Node<SquashfsFileReader>) -> Result<(), BackhandError> {
+        let inode = self.inode(node).ok_or(BackhandError::Unreachable)?;
+        let inode_type = match &inode.inner {
+            InodeInner::BasicDirectory(_) | InodeInner::ExtendedDirectory(_) => NodeType::Dir,
+            InodeInner::BasicFile(_) | InodeInner::ExtendedFile(_) => NodeType::File,
+            InodeInner::BasicSymlink(_) => NodeType::Symlink,
+            InodeInner::BasicBlockDevice(_) => NodeType::BlockDevice,
+            InodeInner::BasicCharacterDevice(_) => NodeType::CharacterDevice,
+        };
+        if inode_type != NodeType::from(&node.inner) {
+            return Err(BackhandError::UnexpectedInode(inode.inner.clone()));
+        }
+        Ok(())
+    }
+
+    /// Read all blocks of `basic_file`, returning the amount of data blocks read
+    fn verify_file_data(
+        &self,
+        basic_file: &BasicFile,
+        fragment_sizes: &[Option<usize>],
+        buf_read: &mut Vec<u8>,
+        buf_decompress: &mut Vec<u8>,
+    ) -> Result<usize, BackhandError> {
+        let fragment = if basic_file.frag_index == NO_FRAGMENT {
+            None
+        } else {
+            let fragments = self.fragments.as_ref().ok_or(BackhandError::MissingFragmentTable)?;
+            let fragment = fragments
+                .get(basic_file.frag_index as usize)
+                .ok_or(BackhandError::CorruptedOrInvalidSquashfs)?;
+            Some((*fragment, basic_file.block_offset))
+        };
+        let layout = BlockLayout {
+            blocks_start: u64::from(basic_file.blocks_start),
+            block_sizes: basic_file.block_sizes.iter().copied().map(BlockSize::from).collect(),
+            fragment,
+            file_size: u64::from(basic_file.file_size),
+        };
+        self.check_layout(&layout)?;
+
+        let mut blocks = 0;
+        let mut remaining = layout.file_size;
+        let mut pos = layout.blocks_start;
+        for block in &layout.block_sizes {
+            let expected = remaining.min(u64::from(self.block_size));
+            remaining -= expected;
+            if block.size == 0 {
+                // sparse block
+                continue;
+            }
+            self.read_layout_block(pos, *block, buf_read, buf_decompress)?;
+            pos += u64::from(block.size);
+            blocks += 1;
+            if (buf_decompress.len() as u64) < expected {
+                return Err(BackhandError::CorruptedOrInvalidSquashfs);
+            }
+        }
+
+        if fragment.is_some() {
+            // already reported while reading the fragment table
+            let fragment_size = fragment_sizes
+                .get(basic_file.frag_index as usize)
+                .copied()
+                .flatten()
+                .ok_or(BackhandError::CorruptedOrInvalidSquashfs)?;
+            if u64::from(basic_file.block_offset) + remaining > fragment_size as u64 {
+                return Err(BackhandError::CorruptedOrInvalidSquashfs);
+            }
+        }
+        Ok(blocks)
+    }
+}
@@ -0,0 +1,149 @@
+//! Extended attributes
+
+use deku::bitvec::Msb0;
+use deku::ctx::Endian;
+use deku::prelude::*;
+use tracing::error;
+
+use crate::error::BackhandError;
+
+/// Size of [`XattrIdTable`] as stored, followed by the pointers to its metadata blocks
+pub(crate) const ID_TABLE_SIZE: usize = 16;
+
+/// Size of [`XattrId`] as stored
+pub(crate) const ID_SIZE: usize = 16;
+
+/// Set in the type of a key, if the value is a reference to a value stored elsewhere
+const VALUE_OOL: u16 = 0x100;
+
+/// Prefix of the name of an [`Xattr`], stored as the type of its key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XattrPrefix {
+    User,
+    Trusted,
+    Security,
+}
+
+impl XattrPrefix {
+    /// Prefix including the trailing `.`, such as `security.`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::User => "user.",
+            Self::Trusted => "trusted.",
+            Self::Security => "security.",
+        }
+    }
+}
+
+/// Extended attribute of a node, such as a SELinux label or file capabilities
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Xattr {
+    pub prefix: XattrPrefix,
+    /// Name without the prefix, such as `selinux` for `security.selinux`
+    pub name: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+impl Xattr {
+    /// Name including the prefix, such as `security.selinux`
+    pub fn full_name(&self) -> Vec<u8> {
+        [self.prefix.as_str().as_bytes(), &self.name].concat()
+    }
+}
+
+/// Header of the xattr id table, pointed to by `xattr_table` of the [`crate::SuperBlock`]
+#[derive(Debug, Copy, Clone, DekuRead, DekuWrite, PartialEq, Eq)]
+#[deku(endian = "type_endian", ctx = "type_endian: deku::ctx::Endian")]
+pub(crate) struct XattrIdTable {
+    /// Start of the metadata blocks holding the keys and values
+    pub(crate) xattr_table_start: u64,
+    pub(crate) xattr_ids: u32,
+    pub(crate) unused: u32,
+}
+
+/// Location of the attributes of the inodes using an xattr index
+#[derive(Debug, Copy, Clone, DekuRead, DekuWrite, PartialEq, Eq)]
+#[deku(endian = "type_endian", ctx = "type_endian: deku::ctx::Endian")]
+pub(crate) struct XattrId {
+    /// Location of the first key: metadata block from `xattr_table_start` in the upper 48 bits,
+    /// and offset into the uncompressed block in the lower 16 bits
+    pub(crate) xattr_ref: u64,
+    pub(crate) count: u32,
+    /// Total size of the keys and values, not including out of line values
+    pub(crate) size: u32,
+}
+
+/// Uncompressed metadata blocks of the keys and values
+pub(crate) struct XattrTable {
+    pub(crate) bytes: Vec<u8>,
+    /// For each metadata block: offset from `xattr_table_start`, and the offset of its bytes in
+    /// `bytes`
+    pub(crate) block_starts: Vec<(u64, usize)>,
+    pub(crate) endian: Endian,
+}
+
+impl XattrTable {
+    /// Decode the attributes of `id`
+    pub(crate) fn xattrs(&self, id: &XattrId) -> Result<Vec<Xattr>, BackhandError> {
+        let mut pos = self.position(id.xattr_ref)?;
+        let mut xattrs = vec![];
+        for _ in 0..id.count {
+            let key_type = self.read_int::<u16>(&mut pos)?;
+            let name_size = self.read_int::<u16>(&mut pos)?;
+            let name = self.read_bytes(&mut pos, usize::from(name_size))?.to_vec();
+
+            let value_size = self.read_int::<u32>(&mut pos)?;
+            let mut value = self.read_bytes(&mut pos, value_size as usize)?;
+            if key_type & VALUE_OOL != 0 {
+                // the value is the location of the actual value
+                let bits = value.view_bits::<Msb0>();
+                let (_, value_ref) = u64::read(bits, self.endian)?;
+                let mut value_pos = self.position(value_ref)?;
+                let value_size = self.read_int::<u32>(&mut value_pos)?;
+                value = self.read_bytes(&mut value_pos, value_size as usize)?;
+            }
+
+            let prefix = match key_type & !VALUE_OOL {
+                0 => XattrPrefix::User,
+                1 => XattrPrefix::Trusted,
+                2 => XattrPrefix::Security,
+                other => {
+                    error!("unknown xattr prefix type {other}");
+                    return Err(BackhandError::CorruptedOrInvalidSquashfs);
+                }
+            };
+            xattrs.push(Xattr { prefix, name, value: value.to_vec() });
+        }
+        Ok(xattrs)
+    }
+
+    /// Convert `xattr_ref` into a position in `bytes`
+    fn position(&self, xattr_ref: u64) -> Result<usize, BackhandError> {
+        let block = xattr_ref >> 16;
+        let offset = (xattr_ref & 0xffff) as usize;
+        let index =
+            self.block_starts.binary_search_by_key(&block, |(start, _)| *start).map_err(|_| {
+                error!("xattr reference {xattr_ref:#x} is not the start of a metadata block");
+                BackhandError::CorruptedOrInvalidSquashfs
+            })?;
+        Ok(self.block_starts[index].1 + offset)
+    }
+
+    fn read_bytes(&self, pos: &mut usize, len: usize) -> Result<&[u8], BackhandError> {
+        let bytes = pos
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(*pos..end))
+            .ok_or(BackhandError::CorruptedOrInvalidSquashfs)?;
+        *pos += len;
+        Ok(bytes)
+    }
+
+    fn read_int<T>(&self, pos: &mut usize) -> Result<T, BackhandError>
+    where
+        T: for<'a> DekuRead<'a, Endian>,
+    {
+        let bytes = self.read_bytes(pos, std::mem::size_of::<T>())?;
+        let (_, value) = T::read(bytes.view_bits::<Msb0>(), self.endian)?;
+        Ok(value)
+    }
+}
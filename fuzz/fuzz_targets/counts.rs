@@ -0,0 +1,32 @@
+#![no_main]
+
+use std::io::Cursor;
+use std::sync::OnceLock;
+
+use backhand::{FilesystemWriter, NodeHeader, Squashfs};
+use libfuzzer_sys::fuzz_target;
+
+/// Small valid image, the table counts of its superblock are replaced by the fuzzer
+fn image() -> &'static [u8] {
+    static IMAGE: OnceLock<Vec<u8>> = OnceLock::new();
+    IMAGE.get_or_init(|| {
+        let mut fs = FilesystemWriter::default();
+        fs.push_dir("dir", NodeHeader::default()).unwrap();
+        fs.push_file(Cursor::new(b"data".to_vec()), "dir/file", NodeHeader::default()).unwrap();
+        let mut image = Cursor::new(vec![]);
+        fs.write(&mut image).unwrap();
+        image.into_inner()
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut image = image().to_vec();
+    // inode_count, frag_count, and id_count of the superblock, in order
+    let counts = (4..8).chain(16..20).chain(26..28);
+    for (pos, byte) in counts.zip(data) {
+        image[pos] = *byte;
+    }
+    if let Ok(squashfs) = Squashfs::from_reader(Cursor::new(image)) {
+        let _ = squashfs.into_filesystem_reader();
+    }
+});